@@ -0,0 +1,124 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use super::frontend::Audio;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("unknown waveform: {}", name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tone {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Tone {
+    pub const DEFAULT_FREQUENCY: f32 = 440.0;
+    pub const DEFAULT_VOLUME: f32 = 0.25;
+
+    pub fn new() -> Self {
+        Self {
+            frequency: Self::DEFAULT_FREQUENCY,
+            volume: Self::DEFAULT_VOLUME,
+            waveform: Waveform::Square,
+        }
+    }
+
+    // `phase` is the position within one period, in 0.0..1.0.
+    pub fn sample(&self, phase: f32) -> f32 {
+        let value = match self.waveform {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        };
+        value * self.volume
+    }
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// XO-CHIP plays its 128-bit sample pattern at 4000 * 2^((pitch - 64) / 48) bits per second.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+pub fn pattern_bit(pattern: &[u8; 16], position: usize) -> bool {
+    let position = position % 128;
+    pattern[position / 8] & (0x80 >> (position % 8)) != 0
+}
+
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn set_buzzer(&mut self, _on: bool) {}
+}
+
+// Remembers the buzzer state of every frame it was told about and the last pattern.
+pub struct CapturingAudio {
+    history: Vec<bool>,
+    pattern: Option<([u8; 16], u8)>,
+}
+
+impl CapturingAudio {
+    pub fn new() -> Self {
+        Self { history: Vec::new(), pattern: None }
+    }
+
+    pub fn history(&self) -> &[bool] {
+        &self.history
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.history.last().copied().unwrap_or(false)
+    }
+
+    pub fn frames_on(&self) -> usize {
+        self.history.iter().filter(|&&on| on).count()
+    }
+
+    // None while the buzzer plays the plain tone.
+    pub fn pattern(&self) -> Option<([u8; 16], u8)> {
+        self.pattern
+    }
+}
+
+impl Default for CapturingAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio for CapturingAudio {
+    fn set_buzzer(&mut self, on: bool) {
+        self.history.push(on);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        self.pattern = pattern;
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use super::debug::{disassemble, set_register, DebugClient, Debugger, Resume, StopReason};
+use super::source_map::SourceMap;
+use super::Emulator;
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_SCOPE: i64 = 1;
+const TIMERS_SCOPE: i64 = 2;
+const STACK_SCOPE: i64 = 3;
+
+// Debug Adapter Protocol server for editors. Messages are read on their own thread
+// and handled once per frame, so the window stays responsive while paused.
+pub struct DapServer {
+    messages: Receiver<Value>,
+    output: Box<dyn Write>,
+    seq: i64,
+    source_map: SourceMap,
+    source_breakpoints: HashMap<PathBuf, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+}
+
+impl DapServer {
+    pub fn new<R: Read + Send + 'static, W: Write + 'static>(input: R, output: W) -> Self {
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if sender.send(message).is_err() { break; }
+            }
+        });
+
+        Self {
+            messages,
+            output: Box::new(output),
+            seq: 1,
+            source_map: SourceMap::new(),
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+        }
+    }
+
+    // Waits for a single client on a local socket.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = source_map;
+    }
+
+    fn send(&mut self, mut message: Value) {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        let _ = write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.output.flush();
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn handle(&mut self, request: &Value, debugger: &mut Debugger, emulator: &mut Emulator) -> bool {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSetVariable": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsSteppingGranularity": false,
+            })),
+            "launch" | "attach" => self.launch(args, emulator),
+            "setBreakpoints" => Ok(self.set_breakpoints(args, debugger)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args, debugger)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                if !self.stop_on_entry { debugger.resume(emulator, Resume::Continue); }
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace(emulator)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_SCOPE, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_SCOPE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_SCOPE, "expensive": false },
+            ] })),
+            "variables" => Ok(json!({ "variables": variables(emulator, args["variablesReference"].as_i64()) })),
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default().to_ascii_lowercase();
+                parse_number(args["value"].as_str().unwrap_or_default())
+                    .ok_or_else(|| "invalid value".to_string())
+                    .and_then(|value| set_register(emulator, &name, value))
+                    .map(|_| json!({ "value": format_variable(emulator, &name) }))
+            }
+            "continue" => {
+                debugger.resume(emulator, Resume::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => { debugger.resume(emulator, Resume::StepOver); Ok(json!({})) }
+            "stepIn" => { debugger.resume(emulator, Resume::Step); Ok(json!({})) }
+            "stepOut" => { debugger.resume(emulator, Resume::StepOut); Ok(json!({})) }
+            "pause" => {
+                debugger.pause();
+                self.respond(request, Ok(json!({})));
+                self.stopped_event("pause", None);
+                return true;
+            }
+            "readMemory" => read_memory(args, emulator),
+            "writeMemory" => write_memory(args, emulator),
+            "disassemble" => Ok(self.disassemble(args, emulator)),
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})));
+                self.event("terminated", json!({}));
+                return false;
+            }
+            _ => Err(format!("unsupported request {}", command)),
+        };
+
+        self.respond(request, result);
+        if command == "initialize" {
+            self.event("initialized", json!({}));
+        }
+        if command == "configurationDone" && self.stop_on_entry {
+            self.stopped_event("entry", None);
+        }
+        true
+    }
+
+    // `program` replaces the loaded rom, `sourceMap` points at an address to line map.
+    fn launch(&mut self, args: &Value, emulator: &mut Emulator) -> Result<Value, String> {
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+        if let Some(program) = args["program"].as_str() {
+            let rom = fs::read(program).map_err(|error| format!("could not read {}: {}", program, error))?;
+            emulator.reset();
+            emulator.load_rom(&rom).map_err(|error| format!("could not load {}: {}", program, error))?;
+            emulator.load_font();
+
+            let default_map = Path::new(program).with_extension("map");
+            if args["sourceMap"].is_null() && default_map.exists() {
+                self.source_map = SourceMap::load(&default_map).map_err(|error| error.to_string())?;
+            }
+        }
+        if let Some(path) = args["sourceMap"].as_str() {
+            self.source_map = SourceMap::load(path).map_err(|error| format!("could not read {}: {}", path, error))?;
+        }
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value, debugger: &mut Debugger) -> Value {
+        let path = PathBuf::from(args["source"]["path"].as_str().unwrap_or_default());
+        for address in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            debugger.remove_breakpoint(address);
+        }
+
+        let mut addresses = Vec::new();
+        let breakpoints: Vec<Value> = args["breakpoints"].as_array().into_iter().flatten().map(|breakpoint| {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            match self.source_map.address(&path, line) {
+                Some(address) => {
+                    debugger.add_breakpoint(address);
+                    addresses.push(address);
+                    json!({ "verified": true, "line": line, "instructionReference": format!("{:#x}", address) })
+                }
+                None => json!({ "verified": false, "line": line, "message": "no code at this line" }),
+            }
+        }).collect();
+
+        self.source_breakpoints.insert(path, addresses);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value, debugger: &mut Debugger) -> Value {
+        for address in self.instruction_breakpoints.drain(..) {
+            debugger.remove_breakpoint(address);
+        }
+
+        let breakpoints: Vec<Value> = args["breakpoints"].as_array().into_iter().flatten().map(|breakpoint| {
+            let reference = breakpoint["instructionReference"].as_str().and_then(parse_number);
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            match reference.map(|address| address as i64 + offset) {
+                Some(address) if address >= 0 => {
+                    debugger.add_breakpoint(address as usize);
+                    self.instruction_breakpoints.push(address as usize);
+                    json!({ "verified": true, "instructionReference": format!("{:#x}", address) })
+                }
+                _ => json!({ "verified": false }),
+            }
+        }).collect();
+
+        json!({ "breakpoints": breakpoints })
+    }
+
+    // The current instruction, then every call site on the stack.
+    fn stack_trace(&self, emulator: &Emulator) -> Value {
+        let addresses = std::iter::once(emulator.pc())
+            .chain(emulator.stack().values().iter().rev().map(|&address| address as usize));
+
+        let frames: Vec<Value> = addresses.enumerate().map(|(id, address)| {
+            let name = match disassemble(emulator, address) {
+                Some((_, instruction, _)) => format!("{:03x}: {}", address, instruction),
+                None => format!("{:03x}", address),
+            };
+            let mut frame = json!({
+                "id": id,
+                "name": name,
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": format!("{:#x}", address),
+            });
+            if let Some((path, line)) = self.source_map.line(address) {
+                frame["source"] = source(path);
+                frame["line"] = json!(line);
+                frame["column"] = json!(1);
+            }
+            frame
+        }).collect();
+
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn disassemble(&self, args: &Value, emulator: &Emulator) -> Value {
+        let base = args["memoryReference"].as_str().and_then(parse_number).unwrap_or(0) as i64
+            + args["offset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0) as usize;
+        // Instructions are assumed to be two bytes wide when counting backwards.
+        let mut address = base + 2 * args["instructionOffset"].as_i64().unwrap_or(0);
+
+        let mut instructions = Vec::new();
+        while instructions.len() < count {
+            let decoded = usize::try_from(address).ok().and_then(|address| disassemble(emulator, address));
+            let mut instruction = match decoded {
+                Some((opcode, instruction, _)) => json!({
+                    "address": format!("{:#x}", address),
+                    "instructionBytes": format!("{:04x}", opcode.number()),
+                    "instruction": instruction.to_string(),
+                }),
+                None => json!({ "address": format!("{:#x}", address), "instruction": "??", "presentationHint": "invalid" }),
+            };
+            if let Some((path, line)) = self.source_map.line(address as usize).filter(|_| address >= 0) {
+                instruction["location"] = source(path);
+                instruction["line"] = json!(line);
+            }
+            instructions.push(instruction);
+            address += decoded.map_or(2, |(_, _, len)| len as i64);
+        }
+        json!({ "instructions": instructions })
+    }
+
+    fn stopped_event(&mut self, reason: &str, text: Option<String>) {
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+}
+
+impl DebugClient for DapServer {
+    fn poll(&mut self, debugger: &mut Debugger, emulator: &mut Emulator) -> bool {
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) if message["type"] == "request" => {
+                    if !self.handle(&message, debugger, emulator) { return false; }
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    fn stopped(&mut self, _debugger: &mut Debugger, _emulator: &mut Emulator, reason: StopReason) {
+        match reason {
+            StopReason::Pause => self.stopped_event("pause", None),
+            StopReason::Step => self.stopped_event("step", None),
+            StopReason::Breakpoint(_) => self.stopped_event("breakpoint", None),
+            StopReason::Watchpoint { address, old, new } => self.stopped_event(
+                "data breakpoint",
+                Some(format!("{:#x}: {:#04x} -> {:#04x}", address, old, new)),
+            ),
+            StopReason::Fault(error) => self.stopped_event("exception", Some(error.to_string())),
+            StopReason::Exit => {
+                self.event("exited", json!({ "exitCode": 0 }));
+                self.event("terminated", json!({}));
+            }
+        }
+    }
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 { return Ok(None); }
+
+        let header = header.trim();
+        if header.is_empty() { break; }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn source(path: &Path) -> Value {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    json!({ "name": name, "path": path.to_string_lossy() })
+}
+
+fn variables(emulator: &Emulator, reference: Option<i64>) -> Vec<Value> {
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+    match reference {
+        Some(REGISTERS_SCOPE) => {
+            let mut list: Vec<Value> = (0..16)
+                .map(|n| variable(format!("V{:X}", n), format_variable(emulator, &format!("v{:x}", n))))
+                .collect();
+            for name in ["I", "PC", "SP"].iter() {
+                list.push(variable(name.to_string(), format_variable(emulator, &name.to_ascii_lowercase())));
+            }
+            list[16]["memoryReference"] = json!(format!("{:#x}", emulator.i()));
+            list
+        }
+        Some(TIMERS_SCOPE) => ["DT", "ST"].iter()
+            .map(|name| variable(name.to_string(), format_variable(emulator, &name.to_ascii_lowercase())))
+            .collect(),
+        Some(STACK_SCOPE) => emulator.stack().values().iter().enumerate().rev()
+            .map(|(index, value)| {
+                let mut entry = variable(format!("[{}]", index), format!("{:#05x}", value));
+                entry["memoryReference"] = json!(format!("{:#x}", value));
+                entry
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn format_variable(emulator: &Emulator, name: &str) -> String {
+    match name {
+        "i" => format!("{:#05x}", emulator.i()),
+        "pc" => format!("{:#05x}", emulator.pc()),
+        "sp" => emulator.stack().pointer().to_string(),
+        "dt" => format!("{:#04x}", emulator.delay_timer()),
+        "st" => format!("{:#04x}", emulator.sound_timer()),
+        _ => {
+            let index = u8::from_str_radix(name.trim_start_matches('v'), 16).unwrap_or(0);
+            format!("{:#04x}", emulator.registers()[index])
+        }
+    }
+}
+
+fn read_memory(args: &Value, emulator: &Emulator) -> Result<Value, String> {
+    let address = memory_address(args)?;
+    let memory = emulator.memory().as_slice();
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let start = address.min(memory.len());
+    let end = address.saturating_add(count).min(memory.len());
+
+    Ok(json!({
+        "address": format!("{:#x}", address),
+        "data": base64_encode(&memory[start..end]),
+        "unreadableBytes": count - (end - start),
+    }))
+}
+
+fn write_memory(args: &Value, emulator: &mut Emulator) -> Result<Value, String> {
+    let address = memory_address(args)?;
+    let data = base64_decode(args["data"].as_str().unwrap_or_default()).ok_or("invalid data")?;
+    let memory = emulator.memory_mut().as_mut_slice();
+    if address + data.len() > memory.len() { return Err("address out of range".to_string()); }
+
+    memory[address..address + data.len()].copy_from_slice(&data);
+    Ok(json!({ "bytesWritten": data.len() }))
+}
+
+fn memory_address(args: &Value) -> Result<usize, String> {
+    let base = args["memoryReference"].as_str().and_then(parse_number).ok_or("invalid memory reference")?;
+    let address = base as i64 + args["offset"].as_i64().unwrap_or(0);
+    usize::try_from(address).map_err(|_| "address out of range".to_string())
+}
+
+// Hex with a 0x prefix, decimal otherwise.
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut group = 0u32;
+    let mut bits = 0;
+    for symbol in text.bytes().filter(|&symbol| symbol != b'=') {
+        let value = BASE64.iter().position(|&known| known == symbol)? as u32;
+        group = group << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
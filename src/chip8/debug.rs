@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+
+use super::error::CpuError;
+use super::instruction::{decode, Instruction};
+use super::opcode::Opcode;
+use super::platform::Platform;
+use super::Emulator;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Pause,
+    Step,
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: u8, new: u8 },
+    Exit,
+    Fault(CpuError),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resume {
+    Continue,
+    Step,
+    // Runs a 2nnn call to its return, other instructions are stepped.
+    StepOver,
+    // Runs until the current subroutine returns.
+    StepOut,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Mode {
+    Paused,
+    Running,
+    Stepping,
+    StepOver { pc: usize, sp: usize },
+    StepOut { sp: usize },
+}
+
+// Breakpoints, watchpoints and the paused/running state shared by every debugger front end.
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    mode: Mode,
+    resumed_at: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            mode: Mode::Paused,
+            resumed_at: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn resume(&mut self, emulator: &Emulator, resume: Resume) {
+        let pc = emulator.pc();
+        self.resumed_at = Some(pc);
+        self.mode = match resume {
+            Resume::Continue => Mode::Running,
+            Resume::Step => Mode::Stepping,
+            Resume::StepOver => match emulator.read_opcode().map(decode) {
+                Ok(Instruction::Call(_)) => Mode::StepOver { pc: pc + 2, sp: emulator.stack().pointer() },
+                _ => Mode::Stepping,
+            },
+            Resume::StepOut => Mode::StepOut { sp: emulator.stack().pointer() },
+        };
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &usize> {
+        self.watchpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.insert(address)
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    // Executes one instruction unless paused; returns why execution stopped, if it did.
+    pub fn step(&mut self, emulator: &mut Emulator) -> Option<StopReason> {
+        if self.is_paused() { return Some(StopReason::Pause); }
+
+        let pc = emulator.pc();
+        if self.resumed_at.take() != Some(pc) && self.breakpoints.contains(&pc) {
+            return Some(self.stop(StopReason::Breakpoint(pc)));
+        }
+
+        let watched: Vec<Option<u8>> = self.watchpoints.iter()
+            .map(|&address| emulator.memory().as_slice().get(address).copied())
+            .collect();
+
+        if let Err(error) = emulator.step() {
+            return Some(self.stop(StopReason::Fault(error)));
+        }
+
+        for (&address, old) in self.watchpoints.iter().zip(watched) {
+            let new = emulator.memory().as_slice().get(address).copied();
+            if let (Some(old), Some(new)) = (old, new) {
+                if old != new {
+                    return Some(self.stop(StopReason::Watchpoint { address, old, new }));
+                }
+            }
+        }
+
+        if !emulator.is_running() {
+            return Some(self.stop(StopReason::Exit));
+        }
+
+        match self.mode {
+            Mode::Stepping => Some(self.stop(StopReason::Step)),
+            Mode::StepOver { pc, sp } if emulator.pc() == pc && emulator.stack().pointer() == sp =>
+                Some(self.stop(StopReason::Step)),
+            Mode::StepOut { sp } if emulator.stack().pointer() < sp => Some(self.stop(StopReason::Step)),
+            _ => None,
+        }
+    }
+
+    fn stop(&mut self, reason: StopReason) -> StopReason {
+        self.mode = Mode::Paused;
+        reason
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Source of debugger commands (terminal, remote protocol) driven by `Emulator::debug`.
+pub trait DebugClient {
+    // Called once per frame, paused or not; returns false to end the session.
+    fn poll(&mut self, debugger: &mut Debugger, emulator: &mut Emulator) -> bool;
+
+    fn stopped(&mut self, debugger: &mut Debugger, emulator: &mut Emulator, reason: StopReason);
+}
+
+// Decodes the instruction at `address`, along with its length in bytes.
+pub fn disassemble(emulator: &Emulator, address: usize) -> Option<(Opcode, Instruction, usize)> {
+    let memory = emulator.memory().as_slice();
+    let bytes = memory.get(address..address.checked_add(2)?)?;
+    let opcode = Opcode::new(u16::from_be_bytes([bytes[0], bytes[1]]));
+    let instruction = decode(opcode);
+    let len = match instruction {
+        Instruction::LdILong if emulator.platform() == Platform::XoChip => 4,
+        _ => 2,
+    };
+    Some((opcode, instruction, len))
+}
+
+// Edits V0-VF, I, PC, SP, DT or ST by their lowercase name.
+pub fn set_register(emulator: &mut Emulator, name: &str, value: usize) -> Result<(), String> {
+    match name {
+        "i" => emulator.set_i(value as u16),
+        "pc" => emulator.set_pc(value),
+        "dt" => emulator.set_delay_timer(value as u8),
+        "st" => emulator.set_sound_timer(value as u8),
+        "sp" => emulator.stack_mut().set_pointer(value).ok_or("stack pointer out of range")?,
+        _ => {
+            let index = name.strip_prefix('v')
+                .and_then(|index| u8::from_str_radix(index, 16).ok())
+                .filter(|&index| index < 16)
+                .ok_or(format!("unknown register {}", name))?;
+            emulator.registers_mut()[index] = value as u8;
+        }
+    }
+    Ok(())
+}
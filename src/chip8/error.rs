@@ -0,0 +1,57 @@
+use std::error;
+use std::fmt;
+
+use super::opcode::Opcode;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CpuError {
+    StackOverflow { pc: usize, opcode: Opcode },
+    StackUnderflow { pc: usize, opcode: Opcode },
+    // `opcode` is the instruction that moved the PC out of memory, when known.
+    PcOutOfRange { pc: usize, opcode: Option<Opcode> },
+    MemoryOutOfRange { pc: usize, opcode: Opcode, address: usize },
+    InvalidOpcode { pc: usize, opcode: Opcode },
+}
+
+impl CpuError {
+    pub fn pc(&self) -> usize {
+        match *self {
+            CpuError::StackOverflow { pc, .. }
+            | CpuError::StackUnderflow { pc, .. }
+            | CpuError::PcOutOfRange { pc, .. }
+            | CpuError::MemoryOutOfRange { pc, .. }
+            | CpuError::InvalidOpcode { pc, .. } => pc,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<Opcode> {
+        match *self {
+            CpuError::StackOverflow { opcode, .. }
+            | CpuError::StackUnderflow { opcode, .. }
+            | CpuError::MemoryOutOfRange { opcode, .. }
+            | CpuError::InvalidOpcode { opcode, .. } => Some(opcode),
+            CpuError::PcOutOfRange { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::StackOverflow { pc, opcode } =>
+                write!(fmt, "stack overflow at pc {:#05x} (op: {:x})", pc, opcode),
+            CpuError::StackUnderflow { pc, opcode } =>
+                write!(fmt, "stack underflow at pc {:#05x} (op: {:x})", pc, opcode),
+            CpuError::PcOutOfRange { pc, opcode: Some(opcode) } =>
+                write!(fmt, "pc out of range: {:#x} (after op: {:x})", pc, opcode),
+            CpuError::PcOutOfRange { pc, opcode: None } =>
+                write!(fmt, "pc out of range: {:#x}", pc),
+            CpuError::MemoryOutOfRange { pc, opcode, address } =>
+                write!(fmt, "memory access out of range: {:#x} at pc {:#05x} (op: {:x})", address, pc, opcode),
+            CpuError::InvalidOpcode { pc, opcode } =>
+                write!(fmt, "invalid opcode at pc {:#05x} (op: {:x})", pc, opcode),
+        }
+    }
+}
+
+impl error::Error for CpuError {}
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use super::palette::Palette;
+use super::video::Video;
+
+// Softens the flicker of sprites that are erased and redrawn every frame.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Filter {
+    #[default]
+    Off,
+    // Pixels light up at once and fade out, keeping this fraction of their
+    // glow from one frame to the next.
+    Phosphor(f32),
+    // Lit pixels are only cleared once they have been off for this many frames.
+    Hold(u32),
+}
+
+impl Filter {
+    pub const NAMES: [&'static str; 3] = ["off", "phosphor", "hold"];
+    pub const PHOSPHOR: f32 = 0.6;
+    pub const HOLD: u32 = 3;
+}
+
+// `off`, `phosphor`, `phosphor=0.8`, `hold` or `hold=5`.
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (name, strength) = match text.split_once('=') {
+            Some((name, strength)) => (name, Some(strength)),
+            None => (text, None),
+        };
+        let invalid = || format!("invalid strength for {}: {}", name, strength.unwrap_or(""));
+        match name {
+            "off" if strength.is_none() => Ok(Filter::Off),
+            "phosphor" => match strength {
+                Some(strength) => match strength.parse::<f32>() {
+                    Ok(strength) if (0.0..=1.0).contains(&strength) => Ok(Filter::Phosphor(strength)),
+                    _ => Err(invalid()),
+                },
+                None => Ok(Filter::Phosphor(Self::PHOSPHOR)),
+            },
+            "hold" => match strength {
+                Some(frames) => frames.parse().map(Filter::Hold).map_err(|_| invalid()),
+                None => Ok(Filter::Hold(Self::HOLD)),
+            },
+            _ => Err(format!("unknown display filter: {}", text)),
+        }
+    }
+}
+
+// Turns frames into rgb24 pixels, remembering as much of the previous frames as the
+// filter needs. The history starts over when the resolution changes.
+pub struct DisplayFilter {
+    filter: Filter,
+    size: (usize, usize),
+    // Phosphor: the color each pixel was last shown with.
+    glow: Vec<[f32; 3]>,
+    // Hold: the palette index shown and the frames it has been off since.
+    held: Vec<(u8, u32)>,
+    rgb: Vec<u8>,
+}
+
+impl DisplayFilter {
+    pub fn new(filter: Filter) -> Self {
+        Self { filter, size: (0, 0), glow: Vec::new(), held: Vec::new(), rgb: Vec::new() }
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.size = (0, 0);
+    }
+
+    // Width and height of the last frame.
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    // The last frame as rgb24, row by row.
+    pub fn rgb(&self) -> &[u8] {
+        &self.rgb
+    }
+
+    // Filters one more frame; call it once per displayed frame.
+    pub fn apply(&mut self, video: &Video, palette: &Palette) -> &[u8] {
+        let size = (video.width(), video.height());
+        if size != self.size {
+            self.size = size;
+            self.glow = Vec::new();
+            self.held = Vec::new();
+        }
+        let pixels = size.0 * size.1;
+        self.rgb.resize(pixels * 3, 0);
+
+        let indices = (0..size.1).flat_map(|y| (0..size.0).map(move |x| (x, y)))
+            .map(|(x, y)| video.color(x, y));
+        match self.filter {
+            Filter::Off => {
+                for (pixel, index) in self.rgb.chunks_mut(3).zip(indices) {
+                    let (r, g, b) = palette.color(index);
+                    pixel.copy_from_slice(&[r, g, b]);
+                }
+            }
+            Filter::Phosphor(strength) => {
+                let fresh = self.glow.is_empty();
+                self.glow.resize(pixels, [0.0; 3]);
+                for ((pixel, glow), index) in self.rgb.chunks_mut(3).zip(self.glow.iter_mut()).zip(indices) {
+                    let (r, g, b) = palette.color(index);
+                    let target = [r as f32, g as f32, b as f32];
+                    for channel in 0..3 {
+                        glow[channel] = if index != 0 || fresh {
+                            target[channel]
+                        } else {
+                            target[channel] + (glow[channel] - target[channel]) * strength
+                        };
+                        pixel[channel] = glow[channel].round() as u8;
+                    }
+                }
+            }
+            Filter::Hold(frames) => {
+                self.held.resize(pixels, (0, 0));
+                for ((pixel, held), index) in self.rgb.chunks_mut(3).zip(self.held.iter_mut()).zip(indices) {
+                    if index != 0 {
+                        *held = (index, 0);
+                    } else if held.0 != 0 {
+                        held.1 += 1;
+                        if held.1 > frames { *held = (0, 0); }
+                    }
+                    let (r, g, b) = palette.color(held.0);
+                    pixel.copy_from_slice(&[r, g, b]);
+                }
+            }
+        }
+        &self.rgb
+    }
+}
+
+impl Default for DisplayFilter {
+    fn default() -> Self {
+        Self::new(Filter::default())
+    }
+}
@@ -0,0 +1,115 @@
+use super::audio::CapturingAudio;
+use super::keyboard::Keyboard;
+use super::palette::Palette;
+use super::video::Video;
+
+pub enum Command {
+    Quit,
+    SaveState(u8),
+    LoadState(u8),
+    // Sent with `true` when the rewind key goes down and `false` when it is released.
+    Rewind(bool),
+    Screenshot,
+}
+
+pub trait Display {
+    // Shows the display; called once per 60 Hz frame, after the timers tick.
+    fn refresh(&mut self, video: &Video);
+
+    // Colors the display is shown with, used for screenshots.
+    fn palette(&self) -> Palette {
+        Palette::new()
+    }
+}
+
+pub trait Input {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> Vec<Command>;
+}
+
+pub trait Audio {
+    fn set_buzzer(&mut self, on: bool);
+
+    // XO-CHIP sample pattern: 128 one-bit samples played back at a rate set by `pitch`.
+    // None goes back to the plain tone of the other platforms.
+    fn set_pattern(&mut self, _pattern: Option<([u8; 16], u8)>) {}
+}
+
+pub trait Frontend: Display + Input + Audio {}
+
+impl<T: Display + Input + Audio> Frontend for T {}
+
+// In-memory frontend for running without a window: keeps the last presented
+// frame, applies staged key presses on poll and captures the buzzer state.
+pub struct Headless {
+    frame: Video,
+    keys: Keyboard,
+    audio: CapturingAudio,
+    refreshes: u64,
+    quit: bool,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Self {
+            frame: Video::new(),
+            keys: Keyboard::new(),
+            audio: CapturingAudio::new(),
+            refreshes: 0,
+            quit: false,
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys.press(key);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.keys.release(key);
+    }
+
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+
+    pub fn frame(&self) -> &Video {
+        &self.frame
+    }
+
+    pub fn refreshes(&self) -> u64 {
+        self.refreshes
+    }
+
+    pub fn audio(&self) -> &CapturingAudio {
+        &self.audio
+    }
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Headless {
+    fn refresh(&mut self, video: &Video) {
+        self.frame = video.clone();
+        self.refreshes += 1;
+    }
+}
+
+impl Input for Headless {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> Vec<Command> {
+        *keyboard = self.keys.clone();
+        if self.quit { vec![Command::Quit] } else { Vec::new() }
+    }
+}
+
+impl Audio for Headless {
+    fn set_buzzer(&mut self, on: bool) {
+        self.audio.set_buzzer(on);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        self.audio.set_pattern(pattern);
+    }
+}
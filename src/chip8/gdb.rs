@@ -0,0 +1,360 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use super::debug::{DebugClient, Debugger, Resume, StopReason};
+use super::error::CpuError;
+use super::Emulator;
+
+// Register numbers as described by target.xml.
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_DT: usize = 19;
+const REGISTER_ST: usize = 20;
+const REGISTER_COUNT: usize = 21;
+
+enum Reply {
+    Packet(String),
+    // Answered later by `stopped`.
+    Deferred,
+    Kill,
+}
+
+// GDB remote serial protocol server on a local TCP port. A client can attach at
+// any time, which pauses the emulator until it continues or detaches.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+}
+
+impl GdbStub {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, client: None, buffer: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn accept(&mut self, debugger: &mut Debugger) {
+        match self.listener.accept() {
+            Ok((stream, peer)) => {
+                if stream.set_nonblocking(true).is_err() { return; }
+                let _ = stream.set_nodelay(true);
+                eprintln!("gdb attached from {}", peer);
+                self.client = Some(stream);
+                self.buffer.clear();
+                debugger.pause();
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+            Err(error) => eprintln!("gdb: {}", error),
+        }
+    }
+
+    fn disconnect(&mut self, debugger: &mut Debugger, emulator: &Emulator) {
+        eprintln!("gdb detached");
+        self.client = None;
+        debugger.resume(emulator, Resume::Continue);
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let client = match &mut self.client { Some(client) => client, None => return Ok(()) };
+        let mut chunk = [0; 1024];
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let client = match &mut self.client { Some(client) => client, None => return Ok(()) };
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        client.set_nonblocking(false)?;
+        let result = write!(client, "${}#{:02x}", data, checksum).and_then(|_| client.flush());
+        client.set_nonblocking(true)?;
+        result
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some(client) = &mut self.client {
+            client.set_nonblocking(false)?;
+            client.write_all(data)?;
+            client.set_nonblocking(true)?;
+        }
+        Ok(())
+    }
+
+    // Takes the next complete packet from the buffer; acks and interrupts are handled on the way.
+    fn next_packet(&mut self, debugger: &mut Debugger) -> io::Result<Option<String>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(b'+') | Some(b'-') => { self.buffer.remove(0); }
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    if !debugger.is_paused() {
+                        debugger.pause();
+                        self.send("S02")?;
+                    }
+                }
+                Some(b'$') => {
+                    let end = match self.buffer.iter().position(|&byte| byte == b'#') {
+                        Some(end) if end + 2 < self.buffer.len() => end,
+                        _ => return Ok(None),
+                    };
+                    let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let expected = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+                    let checksum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+                    if expected != Some(checksum) {
+                        self.send_raw(b"-")?;
+                        continue;
+                    }
+                    self.send_raw(b"+")?;
+                    return Ok(Some(String::from_utf8_lossy(data).into_owned()));
+                }
+                Some(_) => { self.buffer.remove(0); }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str, debugger: &mut Debugger, emulator: &mut Emulator) -> Reply {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => "S05".to_string(),
+            Some(b'g') => (0..REGISTER_COUNT).map(|n| hex(&read_register(emulator, n))).collect(),
+            Some(b'G') => {
+                let mut bytes = unhex(&packet[1..]).unwrap_or_default();
+                for n in 0..REGISTER_COUNT {
+                    let len = register_size(n);
+                    if bytes.len() < len { break; }
+                    write_register(emulator, n, &bytes.drain(..len).collect::<Vec<_>>());
+                }
+                "OK".to_string()
+            }
+            Some(b'p') => match usize::from_str_radix(&packet[1..], 16) {
+                Ok(n) if n < REGISTER_COUNT => hex(&read_register(emulator, n)),
+                _ => "E01".to_string(),
+            },
+            Some(b'P') => {
+                let parsed = packet[1..].split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, unhex(value)?))
+                });
+                match parsed {
+                    Some((n, value)) if n < REGISTER_COUNT && value.len() == register_size(n) => {
+                        write_register(emulator, n, &value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            Some(b'm') => {
+                let memory = emulator.memory();
+                match parse_range(&packet[1..]) {
+                    Some((address, len)) if memory.contains(address, len) => hex(&memory.as_slice()[address..address + len]),
+                    _ => "E01".to_string(),
+                }
+            }
+            Some(b'M') => {
+                let parsed = packet[1..].split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, unhex(data)?)));
+                let memory = emulator.memory_mut();
+                match parsed {
+                    Some(((address, len), data)) if data.len() == len && memory.contains(address, len) => {
+                        memory.as_mut_slice()[address..address + len].copy_from_slice(&data);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            Some(b'c') | Some(b's') => {
+                if let Ok(address) = usize::from_str_radix(&packet[1..], 16) {
+                    emulator.set_pc(address);
+                }
+                let resume = if packet.starts_with('c') { Resume::Continue } else { Resume::Step };
+                debugger.resume(emulator, resume);
+                return Reply::Deferred;
+            }
+            Some(b'Z') | Some(b'z') => {
+                let insert = packet.starts_with('Z');
+                let mut fields = packet[1..].split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(|address| usize::from_str_radix(address, 16).ok());
+                let len = fields.next().and_then(|len| usize::from_str_radix(len, 16).ok()).unwrap_or(1);
+                match (kind, address) {
+                    (Some("0"), Some(address)) | (Some("1"), Some(address)) => {
+                        if insert { debugger.add_breakpoint(address); } else { debugger.remove_breakpoint(address); }
+                        "OK".to_string()
+                    }
+                    (Some("2"), Some(address)) if emulator.memory().contains(address, len) => {
+                        for address in address..address + len {
+                            if insert { debugger.add_watchpoint(address); } else { debugger.remove_watchpoint(address); }
+                        }
+                        "OK".to_string()
+                    }
+                    (Some("2"), Some(_)) => "E01".to_string(),
+                    _ => String::new(),
+                }
+            }
+            Some(b'H') => "OK".to_string(),
+            Some(b'T') => "OK".to_string(),
+            Some(b'k') => return Reply::Kill,
+            Some(b'D') => {
+                let _ = self.send("OK");
+                self.disconnect(debugger, emulator);
+                return Reply::Deferred;
+            }
+            Some(b'q') => self.query(packet),
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=1000;qXfer:features:read+;swbreak+".to_string()
+        } else if let Some(request) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(request) {
+                Some((offset, len)) => {
+                    let xml = target_xml();
+                    let chunk: String = xml.chars().skip(offset).take(len).collect();
+                    let more = offset + len < xml.len();
+                    format!("{}{}", if more { "m" } else { "l" }, chunk)
+                }
+                None => "E01".to_string(),
+            }
+        } else {
+            match packet {
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            }
+        }
+    }
+}
+
+impl DebugClient for GdbStub {
+    fn poll(&mut self, debugger: &mut Debugger, emulator: &mut Emulator) -> bool {
+        if self.client.is_none() {
+            self.accept(debugger);
+            if self.client.is_none() { return true; }
+        }
+
+        // Packets that arrived before the connection closed are still served.
+        let closed = self.receive().is_err();
+
+        loop {
+            let packet = match self.next_packet(debugger) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    if closed { self.disconnect(debugger, emulator); }
+                    return true;
+                }
+                Err(_) => {
+                    self.disconnect(debugger, emulator);
+                    return true;
+                }
+            };
+
+            match self.handle(&packet, debugger, emulator) {
+                Reply::Packet(reply) => {
+                    if self.send(&reply).is_err() {
+                        self.disconnect(debugger, emulator);
+                        return true;
+                    }
+                }
+                Reply::Deferred => {}
+                Reply::Kill => return false,
+            }
+        }
+    }
+
+    fn stopped(&mut self, debugger: &mut Debugger, emulator: &mut Emulator, reason: StopReason) {
+        let reply = match reason {
+            StopReason::Pause => "S02".to_string(),
+            StopReason::Step => "S05".to_string(),
+            StopReason::Breakpoint(_) => "T05swbreak:;".to_string(),
+            StopReason::Watchpoint { address, .. } => format!("T05watch:{:x};", address),
+            StopReason::Exit => "W00".to_string(),
+            StopReason::Fault(CpuError::InvalidOpcode { .. }) => "S04".to_string(),
+            StopReason::Fault(_) => "S0b".to_string(),
+        };
+        if self.send(&reply).is_err() {
+            self.disconnect(debugger, emulator);
+        }
+    }
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        REGISTER_I | REGISTER_PC => 2,
+        _ => 1,
+    }
+}
+
+// Values are sent little endian, in target byte order.
+fn read_register(emulator: &Emulator, n: usize) -> Vec<u8> {
+    match n {
+        0..=15 => vec![emulator.registers()[n as u8]],
+        REGISTER_I => emulator.i().to_le_bytes().to_vec(),
+        REGISTER_PC => (emulator.pc() as u16).to_le_bytes().to_vec(),
+        REGISTER_SP => vec![emulator.stack().pointer() as u8],
+        REGISTER_DT => vec![emulator.delay_timer()],
+        REGISTER_ST => vec![emulator.sound_timer()],
+        _ => Vec::new(),
+    }
+}
+
+fn write_register(emulator: &mut Emulator, n: usize, value: &[u8]) {
+    let word = || u16::from_le_bytes([value[0], value[1]]);
+    match n {
+        0..=15 => emulator.registers_mut()[n as u8] = value[0],
+        REGISTER_I => emulator.set_i(word()),
+        REGISTER_PC => emulator.set_pc(word() as usize),
+        REGISTER_SP => { let _ = emulator.stack_mut().set_pointer(value[0] as usize); }
+        REGISTER_DT => emulator.set_delay_timer(value[0]),
+        REGISTER_ST => emulator.set_sound_timer(value[0]),
+        _ => {}
+    }
+}
+
+fn target_xml() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|n| format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", n))
+        .collect();
+    registers.push("<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>".to_string());
+    registers.push("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>".to_string());
+    for name in ["sp", "dt", "st"].iter() {
+        registers.push(format!("<reg name=\"{}\" bitsize=\"8\" type=\"uint8\"/>", name));
+    }
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\">\
+         <feature name=\"org.rusty-chip-8.cpu\">{}</feature></target>",
+        registers.join("")
+    )
+}
+
+// "addr,len" in hex.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((usize::from_str_radix(address, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) { return None; }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
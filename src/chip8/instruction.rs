@@ -0,0 +1,208 @@
+use std::fmt;
+
+use super::opcode::Opcode;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Instruction {
+    Cls,
+    Ret,
+    Scd(u8),
+    Scu(u8),
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
+    Sys(u16),
+    Jp(u16),
+    Call(u16),
+    SeByte { x: u8, kk: u8 },
+    SneByte { x: u8, kk: u8 },
+    SeReg { x: u8, y: u8 },
+    LdByte { x: u8, kk: u8 },
+    AddByte { x: u8, kk: u8 },
+    LdReg { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    AddReg { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    Shr { x: u8, y: u8 },
+    Subn { x: u8, y: u8 },
+    Shl { x: u8, y: u8 },
+    SneReg { x: u8, y: u8 },
+    SaveRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    LdI(u16),
+    JpV0(u16),
+    Rnd { x: u8, kk: u8 },
+    Drw { x: u8, y: u8, n: u8 },
+    Skp { x: u8 },
+    Sknp { x: u8 },
+    LdVxDt { x: u8 },
+    LdVxK { x: u8 },
+    LdDtVx { x: u8 },
+    LdStVx { x: u8 },
+    AddI { x: u8 },
+    LdF { x: u8 },
+    LdB { x: u8 },
+    LdIVx { x: u8 },
+    LdVxI { x: u8 },
+    LdHf { x: u8 },
+    LdRVx { x: u8 },
+    LdVxR { x: u8 },
+    LdILong,
+    Plane(u8),
+    Audio,
+    Pitch { x: u8 },
+    Invalid(Opcode),
+}
+
+pub fn decode(opcode: Opcode) -> Instruction {
+    let (x, y, n) = (opcode.x(), opcode.y(), opcode.n());
+    let (nnn, kk) = (opcode.nnn(), opcode.kk());
+
+    match opcode.nibbles() {
+        (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+        (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+        (0x0, 0x0, 0xC, _) => Instruction::Scd(n),
+        (0x0, 0x0, 0xD, _) => Instruction::Scu(n),
+        (0x0, 0x0, 0xF, 0xB) => Instruction::Scr,
+        (0x0, 0x0, 0xF, 0xC) => Instruction::Scl,
+        (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+        (0x0, 0x0, 0xF, 0xE) => Instruction::Low,
+        (0x0, 0x0, 0xF, 0xF) => Instruction::High,
+        (0x0, _, _, _) => Instruction::Sys(nnn),
+        (0x1, _, _, _) => Instruction::Jp(nnn),
+        (0x2, _, _, _) => Instruction::Call(nnn),
+        (0x3, _, _, _) => Instruction::SeByte { x, kk },
+        (0x4, _, _, _) => Instruction::SneByte { x, kk },
+        (0x5, _, _, 0x0) => Instruction::SeReg { x, y },
+        (0x5, _, _, 0x2) => Instruction::SaveRange { x, y },
+        (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+        (0x6, _, _, _) => Instruction::LdByte { x, kk },
+        (0x7, _, _, _) => Instruction::AddByte { x, kk },
+        (0x8, _, _, 0x0) => Instruction::LdReg { x, y },
+        (0x8, _, _, 0x1) => Instruction::Or { x, y },
+        (0x8, _, _, 0x2) => Instruction::And { x, y },
+        (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+        (0x8, _, _, 0x4) => Instruction::AddReg { x, y },
+        (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+        (0x8, _, _, 0x6) => Instruction::Shr { x, y },
+        (0x8, _, _, 0x7) => Instruction::Subn { x, y },
+        (0x8, _, _, 0xE) => Instruction::Shl { x, y },
+        (0x9, _, _, 0x0) => Instruction::SneReg { x, y },
+        (0xA, _, _, _) => Instruction::LdI(nnn),
+        (0xB, _, _, _) => Instruction::JpV0(nnn),
+        (0xC, _, _, _) => Instruction::Rnd { x, kk },
+        (0xD, _, _, _) => Instruction::Drw { x, y, n },
+        (0xE, _, 0x9, 0xE) => Instruction::Skp { x },
+        (0xE, _, 0xA, 0x1) => Instruction::Sknp { x },
+        (0xF, 0x0, 0x0, 0x0) => Instruction::LdILong,
+        (0xF, _, 0x0, 0x1) => Instruction::Plane(x),
+        (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
+        (0xF, _, 0x3, 0xA) => Instruction::Pitch { x },
+        (0xF, _, 0x0, 0x7) => Instruction::LdVxDt { x },
+        (0xF, _, 0x0, 0xA) => Instruction::LdVxK { x },
+        (0xF, _, 0x1, 0x5) => Instruction::LdDtVx { x },
+        (0xF, _, 0x1, 0x8) => Instruction::LdStVx { x },
+        (0xF, _, 0x1, 0xE) => Instruction::AddI { x },
+        (0xF, _, 0x2, 0x9) => Instruction::LdF { x },
+        (0xF, _, 0x3, 0x3) => Instruction::LdB { x },
+        (0xF, _, 0x5, 0x5) => Instruction::LdIVx { x },
+        (0xF, _, 0x6, 0x5) => Instruction::LdVxI { x },
+        (0xF, _, 0x3, 0x0) => Instruction::LdHf { x },
+        (0xF, _, 0x7, 0x5) => Instruction::LdRVx { x },
+        (0xF, _, 0x8, 0x5) => Instruction::LdVxR { x },
+        (_, _, _, _) => Instruction::Invalid(opcode),
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Cls => "CLS",
+            Instruction::Ret => "RET",
+            Instruction::Scd(_) => "SCD",
+            Instruction::Scu(_) => "SCU",
+            Instruction::SaveRange { .. } => "SAVE",
+            Instruction::LoadRange { .. } => "LOAD",
+            Instruction::Plane(_) => "PLANE",
+            Instruction::Audio => "AUDIO",
+            Instruction::Pitch { .. } => "PITCH",
+            Instruction::Scr => "SCR",
+            Instruction::Scl => "SCL",
+            Instruction::Exit => "EXIT",
+            Instruction::Low => "LOW",
+            Instruction::High => "HIGH",
+            Instruction::Sys(_) => "SYS",
+            Instruction::Jp(_) | Instruction::JpV0(_) => "JP",
+            Instruction::Call(_) => "CALL",
+            Instruction::SeByte { .. } | Instruction::SeReg { .. } => "SE",
+            Instruction::SneByte { .. } | Instruction::SneReg { .. } => "SNE",
+            Instruction::AddByte { .. } | Instruction::AddReg { .. } | Instruction::AddI { .. } => "ADD",
+            Instruction::Or { .. } => "OR",
+            Instruction::And { .. } => "AND",
+            Instruction::Xor { .. } => "XOR",
+            Instruction::Sub { .. } => "SUB",
+            Instruction::Shr { .. } => "SHR",
+            Instruction::Subn { .. } => "SUBN",
+            Instruction::Shl { .. } => "SHL",
+            Instruction::Rnd { .. } => "RND",
+            Instruction::Drw { .. } => "DRW",
+            Instruction::Skp { .. } => "SKP",
+            Instruction::Sknp { .. } => "SKNP",
+            Instruction::Invalid(_) => "???",
+            _ => "LD",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+
+        match *self {
+            Instruction::Cls | Instruction::Ret | Instruction::Scr | Instruction::Scl
+            | Instruction::Exit | Instruction::Low | Instruction::High => write!(fmt, "{}", mnemonic),
+            Instruction::Scd(n) | Instruction::Scu(n) | Instruction::Plane(n) =>
+                write!(fmt, "{} {}", mnemonic, n),
+            Instruction::SaveRange { x, y } | Instruction::LoadRange { x, y } =>
+                write!(fmt, "{} V{:X} - V{:X}", mnemonic, x, y),
+            Instruction::LdILong => write!(fmt, "LD I, LONG"),
+            Instruction::Audio => write!(fmt, "AUDIO"),
+            Instruction::Pitch { x } => write!(fmt, "PITCH V{:X}", x),
+            Instruction::Sys(nnn) | Instruction::Jp(nnn) | Instruction::Call(nnn) =>
+                write!(fmt, "{} {:#05x}", mnemonic, nnn),
+            Instruction::SeByte { x, kk } | Instruction::SneByte { x, kk }
+            | Instruction::LdByte { x, kk } | Instruction::AddByte { x, kk }
+            | Instruction::Rnd { x, kk } =>
+                write!(fmt, "{} V{:X}, {:#04x}", mnemonic, x, kk),
+            Instruction::SeReg { x, y } | Instruction::LdReg { x, y }
+            | Instruction::Or { x, y } | Instruction::And { x, y }
+            | Instruction::Xor { x, y } | Instruction::AddReg { x, y }
+            | Instruction::Sub { x, y } | Instruction::Subn { x, y }
+            | Instruction::SneReg { x, y } =>
+                write!(fmt, "{} V{:X}, V{:X}", mnemonic, x, y),
+            Instruction::Shr { x, y } | Instruction::Shl { x, y } =>
+                write!(fmt, "{} V{:X} {{, V{:X}}}", mnemonic, x, y),
+            Instruction::LdI(nnn) => write!(fmt, "LD I, {:#05x}", nnn),
+            Instruction::JpV0(nnn) => write!(fmt, "JP V0, {:#05x}", nnn),
+            Instruction::Drw { x, y, n } => write!(fmt, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp { x } | Instruction::Sknp { x } => write!(fmt, "{} V{:X}", mnemonic, x),
+            Instruction::LdVxDt { x } => write!(fmt, "LD V{:X}, DT", x),
+            Instruction::LdVxK { x } => write!(fmt, "LD V{:X}, K", x),
+            Instruction::LdDtVx { x } => write!(fmt, "LD DT, V{:X}", x),
+            Instruction::LdStVx { x } => write!(fmt, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(fmt, "ADD I, V{:X}", x),
+            Instruction::LdF { x } => write!(fmt, "LD F, V{:X}", x),
+            Instruction::LdB { x } => write!(fmt, "LD B, V{:X}", x),
+            Instruction::LdIVx { x } => write!(fmt, "LD [I], V{:X}", x),
+            Instruction::LdVxI { x } => write!(fmt, "LD V{:X}, [I]", x),
+            Instruction::LdHf { x } => write!(fmt, "LD HF, V{:X}", x),
+            Instruction::LdRVx { x } => write!(fmt, "LD R, V{:X}", x),
+            Instruction::LdVxR { x } => write!(fmt, "LD V{:X}, R", x),
+            Instruction::Invalid(opcode) => write!(fmt, "DW {:#06x}", opcode.number()),
+        }
+    }
+}
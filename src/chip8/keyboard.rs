@@ -0,0 +1,58 @@
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone)]
+pub struct Keyboard {
+    memory: [u8; 16],
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self { memory: [0; 16] }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.memory[key as usize] = 1;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.memory[key as usize] = 0;
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.memory[key as usize] == 1
+    }
+
+    pub fn first_pressed_key(&self) -> Option<u8> {
+        self.memory.iter().enumerate().find_map(|(i, &e)| if e == 1 { Some(i as u8) } else { None } )
+    }
+
+    pub fn is_any_key_pressed(&self) -> bool {
+        self.memory.contains(&1)
+    }
+
+    // Bit n set when key n is down.
+    pub fn mask(&self) -> u16 {
+        self.memory.iter().enumerate().fold(0, |mask, (i, &e)| mask | ((e as u16) << i))
+    }
+
+    pub fn set_mask(&mut self, mask: u16) {
+        for (i, e) in self.memory.iter_mut().enumerate() {
+            *e = ((mask >> i) & 1) as u8;
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.memory);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.memory.copy_from_slice(reader.bytes(16)?);
+        Ok(())
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::{Index, IndexMut, Range};
+
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone)]
+pub struct Memory(Vec<u8>);
+impl Memory {
+    pub const DEFAULT_SIZE: usize = 4096;
+
+    pub fn size(&self) -> usize { self.0.len() }
+    pub fn new() -> Self {
+        Self::with_size(Self::DEFAULT_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> Self {
+        Self(vec![0; size])
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
+    pub fn contains(&self, start: usize, len: usize) -> bool {
+        start.checked_add(len).is_some_and(|end| end <= self.size())
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.0.len() as u32);
+        writer.bytes(&self.0);
+    }
+
+    // The saved memory has to be as large as this one.
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let len = reader.u32()? as usize;
+        if len != self.size() { return Err(StateError::Invalid("memory size")); }
+
+        self.0.copy_from_slice(reader.bytes(len)?);
+        Ok(())
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<u16> for Memory {
+    type Output = u8;
+
+    fn index(&self, i: u16) -> &Self::Output {
+        &self.0[i as usize]
+    }
+}
+
+impl IndexMut<u16> for Memory {
+    fn index_mut(&mut self, i: u16) -> &mut Self::Output {
+        &mut self.0[i as usize]
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = u8;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.0[i]
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.0[i]
+    }
+}
+
+impl Index<Range<usize>> for Memory {
+    type Output = [u8];
+
+    fn index(&self, index: Range<usize>) -> &[u8] {
+        &self.0[..][index]
+    }
+}
+
+impl Index<Range<u16>> for Memory {
+    type Output = [u8];
+
+    fn index(&self, index: Range<u16>) -> &[u8] {
+        &self.0[..][(index.start as usize)..(index.end as usize)]
+    }
+}
@@ -1,749 +1,1138 @@
-extern crate sdl2;
-
-use sdl2::pixels::Color;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::rect::Rect;
-
-use std::fmt;
-use std::ops::{Index, IndexMut, RangeTo, Range, RangeInclusive};
+mod audio;
+mod checksum;
+mod dap;
+mod debug;
+mod error;
+mod filter;
+mod font;
+mod frontend;
+mod gdb;
+mod instruction;
+mod keyboard;
+mod memory;
+mod movie;
+mod opcode;
+mod palette;
+mod platform;
+mod quirks;
+mod random;
+mod recording;
+mod registers;
+mod repl;
+mod rewind;
+mod screenshot;
+#[cfg(feature = "sdl")]
+mod sdl;
+mod source_map;
+mod stack;
+mod state;
+mod trace;
+mod trace_diff;
+mod video;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use rand::prelude::*;
-use std::process::exit;
-
-struct PixelSize {
-    width: u32,
-    height: u32,
+use movie::MovieSession;
+
+pub use audio::{pattern_bit, pattern_rate, CapturingAudio, NullAudio, Tone, Waveform};
+pub use checksum::crc32;
+pub use dap::DapServer;
+pub use debug::{disassemble, set_register, DebugClient, Debugger, Resume, StopReason};
+pub use error::CpuError;
+pub use filter::{DisplayFilter, Filter};
+pub use font::Font;
+pub use frontend::{Audio, Command, Display, Frontend, Headless, Input};
+pub use gdb::GdbStub;
+pub use instruction::{decode, Instruction};
+pub use keyboard::Keyboard;
+pub use memory::Memory;
+pub use movie::{Movie, MovieError, MovieMode};
+pub use opcode::Opcode;
+pub use palette::Palette;
+pub use platform::Platform;
+pub use quirks::Quirks;
+pub use random::{Random, RandomMode};
+pub use recording::{Recorder, RecordingFormat};
+pub use registers::Registers;
+pub use repl::Repl;
+pub use rewind::Rewind;
+pub use screenshot::Image;
+#[cfg(feature = "sdl")]
+pub use sdl::SdlFrontend;
+pub use source_map::SourceMap;
+pub use stack::Stack;
+pub use state::{StateError, StateReader, StateWriter};
+pub use trace::{TraceFilter, TraceLevel, Tracer};
+pub use trace_diff::{diff_traces, Difference, TraceDiff, TraceFormat, TraceReader, TraceRecord};
+pub use video::Video;
+
+#[derive(Clone)]
+pub struct Emulator {
+    font: Font,
+    video: Video,
+    keyboard: Keyboard,
+    registers: Registers,
+    memory: Memory,
+    stack: Stack,
+    pc: usize,
+    i: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    hz: u32,
+    quirks: Quirks,
+    platform: Platform,
+    vblank_wait: bool,
+    key_wait: Option<u8>,
+    rpl: [u8; 16],
+    halted: bool,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    audio_changed: bool,
+    state_slots: Option<PathBuf>,
+    rewind_frames: usize,
+    random: Random,
+    rom_crc: u32,
+    movie: Option<MovieSession>,
+    cycles: u64,
+    tracer: Option<Arc<Mutex<Tracer>>>,
+    screenshots: Option<PathBuf>,
+    screenshot_scale: u32,
+    screenshot_after: Option<(u64, PathBuf)>,
+    recorder: Option<Arc<Mutex<Recorder>>>,
 }
 
-struct Video {
-    canvas: sdl2::render::Canvas<sdl2::video::Window>,
-    memory: [u64; 32],
-    draw_flag: bool,
-    pixinfo: PixelSize,
-}
+impl Emulator {
+    pub const ROM_START: u16 = 512;
+    pub const TIMER_HZ: u32 = 60;
+    pub const HZ: u32 = 600;
+    pub const DEFAULT_PITCH: u8 = 64;
+    pub const REWIND_SECONDS: u32 = 10;
 
-impl Video {
-    const BLACK: sdl2::pixels::Color = Color::RGB(0, 0, 0);
-    const WHITE: sdl2::pixels::Color = Color::RGB(255, 255, 255);
+    pub fn new() -> Self {
+        Self {
+            font: Font::new(),
+            video: Video::new(),
+            keyboard: Keyboard::new(),
+            registers: Registers::new(),
+            memory: Memory::new(),
+            stack: Stack::new(),
+            pc: 0,
+            i: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            hz: Self::HZ,
+            quirks: Quirks::default(),
+            platform: Platform::default(),
+            vblank_wait: false,
+            key_wait: None,
+            rpl: [0; 16],
+            halted: false,
+            audio_pattern: [0; 16],
+            pitch: Self::DEFAULT_PITCH,
+            audio_changed: false,
+            state_slots: None,
+            rewind_frames: (Self::REWIND_SECONDS * Self::TIMER_HZ) as usize,
+            random: Random::new(RandomMode::default(), rand::random()),
+            rom_crc: 0,
+            movie: None,
+            cycles: 0,
+            tracer: None,
+            screenshots: None,
+            screenshot_scale: 1,
+            screenshot_after: None,
+            recorder: None,
+        }
+    }
 
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        let width = 800;
-        let height = 600;
+    pub fn run<F: Frontend>(&mut self, frontend: &mut F) -> Result<(), CpuError> {
+        self.run_loop(frontend, None)
+    }
 
-        let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem.window("rusty-chip-8", width, height)
-            .position_centered()
-            .build()
-            .unwrap();
+    // Runs under a debugger: breakpoints pause emulation while the window keeps being served.
+    pub fn debug<F: Frontend>(
+        &mut self,
+        frontend: &mut F,
+        debugger: &mut Debugger,
+        client: &mut dyn DebugClient,
+    ) -> Result<(), CpuError> {
+        self.run_loop(frontend, Some((debugger, client)))
+    }
 
-        let mut canvas = window.into_canvas().build().unwrap();
-        canvas.present();
+    fn run_loop<F: Frontend>(
+        &mut self,
+        frontend: &mut F,
+        mut debug: Option<(&mut Debugger, &mut dyn DebugClient)>,
+    ) -> Result<(), CpuError> {
+        let frame_duration = Duration::from_nanos(1_000_000_000 / Self::TIMER_HZ as u64);
+        let mut next_frame = Instant::now();
+        let mut rewind = Rewind::new(self.rewind_frames);
+        let mut rewinding = false;
+        let mut frames = 0;
+        // Cycles run so far in the current frame.
+        let mut frame_cycles = 0;
 
-        Self {
-            canvas,
-            memory: [0; 32],
-            draw_flag: true,
-            pixinfo: PixelSize {
-                width: width / 64,
-                height: height / 32,
+        while self.is_running() {
+            let keys = self.keyboard.mask();
+            let commands = frontend.poll(&mut self.keyboard);
+            self.trace_keys(keys);
+            for command in commands {
+                match command {
+                    Command::Quit => return Ok(()),
+                    Command::SaveState(slot) => self.save_slot(slot),
+                    Command::LoadState(_) | Command::Rewind(true) if self.movie.is_some() => {
+                        eprintln!("states can not be changed while a movie is active");
+                    }
+                    Command::LoadState(slot) => self.load_slot(slot),
+                    Command::Rewind(held) => rewinding = held,
+                    Command::Screenshot => self.save_screenshot(&frontend.palette()),
+                }
+            }
+
+            if self.movie.as_ref().is_some_and(MovieSession::is_finished) {
+                eprintln!("movie finished");
+                self.movie = None;
+            }
+
+            if let Some((debugger, client)) = &mut debug {
+                if !client.poll(debugger, self) { return Ok(()); }
+            }
+            let paused = debug.as_ref().is_some_and(|(debugger, _)| debugger.is_paused());
+
+            if rewinding {
+                self.rewind_frame(&mut rewind);
+            } else if !paused {
+                if frame_cycles == 0 { self.begin_movie_frame(); }
+                while frame_cycles < self.cycles_per_frame() {
+                    let cycles = self.cycles;
+                    let mut stopped = false;
+                    match &mut debug {
+                        Some((debugger, client)) => {
+                            if let Some(reason) = debugger.step(self) {
+                                client.stopped(debugger, self, reason);
+                                // The client may resume at once, e.g. for the next of several steps.
+                                stopped = debugger.is_paused();
+                            }
+                        }
+                        None => self.step()?,
+                    }
+                    frame_cycles += (self.cycles - cycles) as u32;
+                    if stopped || self.vblank_wait { break; }
+                }
+                // Timers and the movie only advance with whole frames; a pause in the middle
+                // of one leaves the rest of its cycles for when emulation goes on.
+                if frame_cycles >= self.cycles_per_frame() || self.vblank_wait {
+                    frame_cycles = 0;
+                    self.tick_timers();
+                    if let Err(error) = self.end_movie_frame() {
+                        eprintln!("{}", error);
+                        self.movie = None;
+                    }
+                    rewind.record(self);
+                    frames += 1;
+                    self.screenshot_frame(frames, &frontend.palette());
+                }
             }
+            if self.audio_changed {
+                self.audio_changed = false;
+                let pattern = if self.platform == Platform::XoChip { Some((self.audio_pattern, self.pitch)) } else { None };
+                frontend.set_pattern(pattern);
+            }
+            frontend.set_buzzer(!rewinding && self.is_buzzing());
+            // Presented once per frame, even when unchanged, so filters keep fading.
+            frontend.refresh(&self.video);
+            self.record_frame(&frontend.palette());
+
+            next_frame += frame_duration;
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+        Ok(())
+    }
+
+    // Steps back one recorded frame; the live keypad is kept so held keys stay held.
+    fn rewind_frame(&mut self, rewind: &mut Rewind) {
+        let keyboard = self.keyboard.clone();
+        match rewind.step_back(self) {
+            Ok(true) => self.keyboard = keyboard,
+            Ok(false) => {}
+            Err(error) => eprintln!("could not rewind: {}", error),
+        }
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        for _ in 0..self.cycles_per_frame() {
+            self.step()?;
+            if self.vblank_wait { break; }
         }
+        self.tick_timers();
+        Ok(())
     }
 
-    pub fn clear(&mut self) {
-        self.memory = [0; 32];
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 
-    pub fn draw_sprite(&mut self, sprite: &Vec<u8>, x: u8, y: u8) -> u8 {
-        self.draw_flag = true;
-        let mut collision : u8 = 0;
+    pub fn is_buzzing(&self) -> bool {
+        self.sound_timer > 0
+    }
 
-        for (sprite_line_index, sprite_pixel) in sprite.iter().enumerate() {
-            let line_num = y + sprite_line_index as u8;
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
 
-            for xi in 0..=7 {
-                if sprite_pixel & (0x80 >> xi) != 0 {
-                    let offset = 63 - x - xi;
-                    let display_bit_p = 1 << offset;
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
 
-                    if (self.memory[line_num as usize] & display_bit_p) > 0 { collision = 1; }
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
 
-                    self.memory[line_num as usize] = self.memory[line_num as usize] ^ display_bit_p;
-                }
-            }
-        }
+    // Also resizes memory for the platform, so it has to be called before loading.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.memory = Memory::with_size(platform.memory_size());
+    }
+
+    pub fn random(&self) -> &Random {
+        &self.random
+    }
 
-        collision
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
     }
 
-    pub fn refresh(&mut self) {
-        if !self.draw_flag { return; }
+    pub fn audio_pattern(&self) -> (&[u8; 16], u8) {
+        (&self.audio_pattern, self.pitch)
+    }
 
-        self.draw_flag = false;
-        self.canvas.clear();
+    // Length of the history kept for rewinding, 0 disables it.
+    pub fn set_rewind_seconds(&mut self, seconds: u32) {
+        self.rewind_frames = (seconds * Self::TIMER_HZ) as usize;
+    }
 
-        for (line_index, line) in self.memory.iter().enumerate() {
-            for pixel_index in 0..64 {
-                let offset = 63 - pixel_index;
-                let pixel_value = (line & (1_u64 << offset)) >> offset;
+    pub fn hz(&self) -> u32 {
+        self.hz
+    }
 
-                if pixel_value == 1 {
-                    self.canvas.set_draw_color(Self::BLACK);
-                } else {
-                    self.canvas.set_draw_color(Self::WHITE);
-                }
+    pub fn set_hz(&mut self, hz: u32) {
+        self.hz = hz;
+    }
 
-                let rect = Rect::new(
-                    pixel_index * self.pixinfo.width as i32,
-                    line_index as i32 * self.pixinfo.height as i32,
-                    self.pixinfo.width, self.pixinfo.height);
+    pub fn cycles_per_frame(&self) -> u32 {
+        (self.hz / Self::TIMER_HZ).max(1)
+    }
 
-                self.canvas.fill_rect(rect);
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        let (pc, i) = (self.pc, self.i);
+        let opcode = self.read_opcode();
+        let result = opcode.and_then(|opcode| {
+            self.exec_opcode(&opcode)?;
+            if self.pc + 1 >= self.memory.size() {
+                return Err(CpuError::PcOutOfRange { pc: self.pc, opcode: Some(opcode) });
+            }
+            Ok(())
+        });
+        self.cycles += 1;
+
+        if let Some(tracer) = &self.tracer {
+            let mut tracer = tracer.lock().unwrap();
+            if let Ok(opcode) = opcode {
+                tracer.instruction(self, pc, opcode, i, &self.instruction(opcode));
+            }
+            if let Err(error) = &result {
+                tracer.fault(self.cycles, error);
             }
         }
-
-        self.canvas.present();
+        result
     }
-}
 
-struct Font {
-    memory: [u8; 80],
-}
+    // Instructions executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
 
-impl Font {
-    pub const START: u16 = 120;
-    pub const DEFAULT: [u8; 80] = [
-        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-        0x20, 0x60, 0x20, 0x20, 0x70, // 1
-        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-        0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-    ];
+    // Shared by clones of the emulator, so a trace continues across state loads.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer.map(|tracer| Arc::new(Mutex::new(tracer)));
+    }
 
-    pub fn new() -> Self {
-        Self { memory: Self::DEFAULT }
+    pub fn flush_trace(&self) {
+        if let Some(tracer) = &self.tracer {
+            let _ = tracer.lock().unwrap().flush();
+        }
     }
-}
 
-struct Stack {
-    values: [u16; 16],
-    pointer: usize,
-}
+    fn trace_keys(&self, previous: u16) {
+        let tracer = match &self.tracer { Some(tracer) => tracer, None => return };
+        let changed = previous ^ self.keyboard.mask();
+        if changed == 0 { return; }
 
-impl Stack {
-    pub fn new() -> Self {
-        Self {
-            values: [0; 16],
-            pointer: 0,
+        let mut tracer = tracer.lock().unwrap();
+        for key in (0..16).filter(|key| changed & (1 << key) != 0) {
+            tracer.key(self.cycles, key, self.keyboard.is_key_pressed(key));
         }
     }
 
-    pub fn push(&mut self, value: u16) {
-        self.values[self.pointer] = value;
-        self.pointer = self.pointer + 1;
+    pub fn video(&self) -> &Video {
+        &self.video
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.pointer = self.pointer - 1;
-        self.values[self.pointer]
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
     }
-}
 
-impl fmt::Display for Stack {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, " stack_p: {}, stack_value: {:#x}, ", self.pointer, self.values[self.pointer])
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
     }
-}
 
-impl Index<usize> for Stack {
-    type Output = u16;
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
 
-    fn index(&self, i: usize) -> &Self::Output {
-        &self.values[i]
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
     }
-}
 
-impl IndexMut<usize> for Stack {
-    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        &mut self.values[i]
+    pub fn registers(&self) -> &Registers {
+        &self.registers
     }
-}
 
-struct Memory([u8; 4096]);
-impl Memory {
-    pub fn size(&self) -> usize { 4096 }
-    pub fn new() -> Self {
-        Self([0; 4096])
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
     }
-}
 
-impl Index<u16> for Memory {
-    type Output = u8;
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
 
-    fn index(&self, i: u16) -> &Self::Output {
-        &self.0[i as usize]
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
     }
-}
 
-impl IndexMut<u16> for Memory {
-    fn index_mut(&mut self, i: u16) -> &mut Self::Output {
-        &mut self.0[i as usize]
+    pub fn pc(&self) -> usize {
+        self.pc
     }
-}
 
-impl Index<usize> for Memory {
-    type Output = u8;
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
 
-    fn index(&self, i: usize) -> &Self::Output {
-        &self.0[i]
+    pub fn i(&self) -> u16 {
+        self.i
     }
-}
 
-impl IndexMut<usize> for Memory {
-    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        &mut self.0[i]
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
     }
-}
 
-impl Index<Range<usize>> for Memory {
-    type Output = [u8];
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
 
-    fn index(&self, index: Range<usize>) -> &[u8] {
-        &self.0[..][index]
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
     }
-}
 
-impl Index<Range<u16>> for Memory {
-    type Output = [u8];
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
 
-    fn index(&self, index: Range<u16>) -> &[u8] {
-        &self.0[..][(index.start as usize)..(index.end as usize)]
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
     }
-}
 
-struct Registers([u8; 16]);
-impl Registers {
-    pub fn new() -> Self {
-        Self([0; 16])
+    // Powers the machine back on, keeping the platform, quirks, speed, random seed and
+    // everything attached from the command line. Load a rom and the font afterwards.
+    pub fn reset(&mut self) {
+        self.video = Video::new();
+        self.keyboard = Keyboard::new();
+        self.registers = Registers::new();
+        self.memory = Memory::with_size(self.platform.memory_size());
+        self.stack = Stack::new();
+        self.pc = 0;
+        self.i = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.vblank_wait = false;
+        self.key_wait = None;
+        self.rpl = [0; 16];
+        self.halted = false;
+        self.audio_pattern = [0; 16];
+        self.pitch = Self::DEFAULT_PITCH;
+        self.audio_changed = true;
+        self.random.reseed(self.random.seed());
+        self.rom_crc = 0;
+        self.cycles = 0;
     }
-}
 
-impl Index<u8> for Registers {
-    type Output = u8;
+    // Fails, leaving memory as it was, when the rom doesn't fit the platform's memory.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        let ustart = Self::ROM_START as usize;
+        let room = self.memory.size() - ustart;
+        if rom.len() > room {
+            return Err(format!("rom is {} bytes, only {} fit in memory", rom.len(), room));
+        }
 
-    fn index(&self, i: u8) -> &Self::Output {
-        &self.0[i as usize]
+        self.rom_crc = crc32(rom);
+        for (i, e) in rom.iter().enumerate() { self.memory[ustart + i] = *e; }
+        self.pc = ustart;
+        Ok(())
     }
-}
 
-impl Index<u16> for Registers {
-    type Output = u8;
+    pub fn load_font(&mut self) {
+        for (i, e) in self.font.memory.iter().enumerate() {
+            self.memory[Font::START as usize + i] = *e;
+        }
+        for (i, e) in self.font.big.iter().enumerate() {
+            self.memory[Font::BIG_START as usize + i] = *e;
+        }
+    }
 
-    fn index(&self, i: u16) -> &Self::Output {
-        &self.0[i as usize]
+    pub fn save_state(&self) -> Vec<u8> {
+        state::wrap(&self.snapshot())
     }
-}
 
-impl IndexMut<u16> for Registers {
-    fn index_mut(&mut self, i: u16) -> &mut Self::Output {
-        &mut self.0[i as usize]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let (payload, version) = state::unwrap(data)?;
+        self.restore_version(payload, version)
     }
-}
 
-impl IndexMut<u8> for Registers {
-    fn index_mut(&mut self, i: u8) -> &mut Self::Output {
-        &mut self.0[i as usize]
+    // Bare state payload without the file header and checksum.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.write_state(&mut writer);
+        writer.into_inner()
+    }
+
+    pub fn restore(&mut self, payload: &[u8]) -> Result<(), StateError> {
+        self.restore_version(payload, state::VERSION)
     }
-}
 
-impl Index<Range<usize>> for Registers {
-    type Output = [u8];
+    // Leaves the emulator untouched when the state can not be restored.
+    fn restore_version(&mut self, payload: &[u8], version: u16) -> Result<(), StateError> {
+        let mut reader = StateReader::new(payload, version);
+        let mut restored = self.clone();
+        restored.read_state(&mut reader)?;
+        if !reader.is_empty() { return Err(StateError::Invalid("trailing data")); }
 
-    fn index(&self, index: Range<usize>) -> &[u8] {
-        &self.0[..][index]
+        *self = restored;
+        Ok(())
     }
-}
 
-impl Index<RangeInclusive<usize>> for Registers {
-    type Output = [u8];
+    pub fn save_state_file<P: AsRef<Path>>(&self, path: P) -> Result<(), StateError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
 
-    fn index(&self, index: RangeInclusive<usize>) -> &[u8] {
-        &self.0[..][index]
+    pub fn load_state_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), StateError> {
+        let data = fs::read(path)?;
+        self.load_state(&data)
     }
-}
 
-impl Index<RangeTo<usize>> for Registers {
-    type Output = [u8];
+    // Slot `n` is stored next to `base` as `<base>.state<n>`.
+    pub fn set_state_slots<P: AsRef<Path>>(&mut self, base: P) {
+        self.state_slots = Some(base.as_ref().to_path_buf());
+    }
 
-    fn index(&self, index: RangeTo<usize>) -> &[u8] {
-        &self.0[..][index]
+    pub fn state_slot_path(&self, slot: u8) -> Option<PathBuf> {
+        self.state_slots.as_ref().map(|base| {
+            let mut name = base.as_os_str().to_owned();
+            name.push(format!(".state{}", slot));
+            PathBuf::from(name)
+        })
+    }
+
+    fn save_slot(&self, slot: u8) {
+        if let Some(path) = self.state_slot_path(slot) {
+            match self.save_state_file(&path) {
+                Ok(()) => eprintln!("saved state to {}", path.display()),
+                Err(error) => eprintln!("could not save {}: {}", path.display(), error),
+            }
+        }
     }
-}
 
-impl fmt::LowerHex for Registers {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "[");
-        for e in self.0.iter() { write!(fmt, "{:x}, ", *e); }
-        write!(fmt, "]")
+    fn load_slot(&mut self, slot: u8) {
+        if let Some(path) = self.state_slot_path(slot) {
+            match self.load_state_file(&path) {
+                Ok(()) => eprintln!("loaded state from {}", path.display()),
+                Err(error) => eprintln!("could not load {}: {}", path.display(), error),
+            }
+        }
     }
-}
 
-struct Keyboard {
-    event_pump: sdl2::EventPump,
-    bindings: [(u8, Keycode); 16],
-    memory: [u8; 16],
-}
+    // Hotkey screenshots are numbered next to `base` as `<base>.<n>.png`.
+    pub fn set_screenshots<P: AsRef<Path>>(&mut self, base: P, scale: u32) {
+        self.screenshots = Some(base.as_ref().to_path_buf());
+        self.screenshot_scale = scale;
+    }
 
-impl Keyboard {
-    // (Chip8 key, keyboard key)
-    const BINDINGS: [(u8, Keycode); 16] = [
-        (0x1, Keycode::Num1),
-        (0x2, Keycode::Num2),
-        (0x3, Keycode::Num3),
-        (0xC, Keycode::Num4),
-
-        (0x4, Keycode::Q),
-        (0x5, Keycode::W),
-        (0x6, Keycode::E),
-        (0xD, Keycode::R),
-
-        (0x7, Keycode::A),
-        (0x8, Keycode::S),
-        (0x9, Keycode::D),
-        (0xE, Keycode::F),
-
-        (0xA, Keycode::Z),
-        (0x0, Keycode::X),
-        (0xB, Keycode::C),
-        (0xF, Keycode::V),
-    ];
-
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        Self {
-            event_pump: sdl_context.event_pump().unwrap(),
-            bindings: Self::BINDINGS,
-            memory: [0; 16]
+    pub fn screenshot_after<P: AsRef<Path>>(&mut self, frames: u64, path: P) {
+        self.screenshot_after = Some((frames, path.as_ref().to_path_buf()));
+    }
+
+    pub fn screenshot(&self, palette: &Palette) -> Image {
+        Image::capture(&self.video, palette, self.screenshot_scale)
+    }
+
+    fn save_screenshot(&self, palette: &Palette) {
+        let base = match &self.screenshots {
+            Some(base) => base.as_os_str().to_owned(),
+            None => return,
+        };
+        let path = (1..).map(|n| {
+            let mut name = base.clone();
+            name.push(format!(".{}.png", n));
+            PathBuf::from(name)
+        }).find(|path| !path.exists()).unwrap();
+
+        match self.screenshot(palette).save(&path) {
+            Ok(()) => eprintln!("saved screenshot to {}", path.display()),
+            Err(error) => eprintln!("could not save {}: {}", path.display(), error),
         }
     }
 
-    pub fn is_key_pressed(&self, key: u8) -> bool {
-        self.memory[key as usize] == 1
+    fn screenshot_frame(&mut self, frame: u64, palette: &Palette) {
+        if !matches!(&self.screenshot_after, Some((after, _)) if *after == frame) { return; }
+
+        let (_, path) = self.screenshot_after.take().unwrap();
+        if let Err(error) = self.screenshot(palette).save(&path) {
+            eprintln!("could not save {}: {}", path.display(), error);
+        }
     }
 
-    pub fn first_pressed_key(&self) -> Option<u8> {
-        self.memory.iter().enumerate().find_map(|(i, &e)| if e == 1 { Some(i as u8) } else { None } )
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder.map(|recorder| Arc::new(Mutex::new(recorder)));
     }
 
-    pub fn is_any_key_pressed(&self) -> bool {
-        self.memory.iter().any(|&e| e == 1)
+    // Captures the display for the recorder, which is dropped when writing fails.
+    pub fn record_frame(&mut self, palette: &Palette) {
+        let result = match &self.recorder {
+            Some(recorder) => recorder.lock().unwrap().frame(&self.video, palette),
+            None => return,
+        };
+        if let Err(error) = result {
+            eprintln!("recording stopped: {}", error);
+            self.recorder = None;
+        }
     }
 
-    pub fn pool(&mut self) {
-        for event in self.event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    exit(0);
-                },
-                Event::KeyDown { keycode, ..} => {
-                    println!("DOWN: {:?}", keycode.unwrap());
+    // Detaches the recorder and completes its file, giving the number of frames captured.
+    pub fn finish_recording(&mut self) -> Option<io::Result<u64>> {
+        self.recorder.take().map(|recorder| {
+            let mut recorder = recorder.lock().unwrap();
+            recorder.finish().map(|()| recorder.frames())
+        })
+    }
 
-                    let result = self.bindings.iter().find_map(|&(int, ext)| {
-                      if keycode.unwrap() == ext { Some(int) } else { None }
-                    });
+    pub fn rom_crc(&self) -> u32 {
+        self.rom_crc
+    }
 
-                    match result {
-                        Some(internal_number) => { self.memory[internal_number as usize] = 1; },
-                        _ => {}
-                    }
-                },
-                Event::KeyUp { keycode, ..} => {
-                    println!("UP: {:?}", keycode.unwrap());
+    pub fn state_hash(&self) -> u32 {
+        crc32(&self.snapshot())
+    }
 
-                    let result = self.bindings.iter().find_map(|&(int, ext)| {
-                        if keycode.unwrap() == ext { Some(int) } else { None }
-                    });
+    // Starts recording keypad input; movies are replayed from power-on.
+    pub fn record_movie(&mut self) {
+        let movie = Movie::new(self);
+        self.movie = Some(MovieSession { movie, mode: MovieMode::Recording, frame: 0 });
+    }
 
-                    match result {
-                        Some(internal_number) => { self.memory[internal_number as usize] = 0; },
-                        _ => {}
-                    }
-                },
-                _ => {}
-            }
+    // Expects the emulator to be set up by `Movie::configure` with the rom loaded.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        if movie.rom_crc != self.rom_crc {
+            return Err(MovieError::RomMismatch { expected: movie.rom_crc, actual: self.rom_crc });
         }
+        self.movie = Some(MovieSession { movie, mode: MovieMode::Replaying, frame: 0 });
+        Ok(())
     }
-}
 
-pub struct Emulator {
-    font: Font,
-    video: Video,
-    keyboard: Keyboard,
-    registers: Registers,
-    memory: Memory,
-    stack: Stack,
-    pc: usize,
-    i: u16,
-    delay_timer: u16,
-}
+    // Detaches the movie, a recording ends at the current frame.
+    pub fn finish_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|mut session| {
+            if session.mode == MovieMode::Recording {
+                session.movie.set_length(session.frame);
+            }
+            session.movie
+        })
+    }
 
-impl Emulator {
-    const ROM_START: u16 = 512;
-    const TIMER_HZ: u32 = 10;
-    const HZ: u32 = 20;
+    pub fn movie_mode(&self) -> Option<MovieMode> {
+        self.movie.as_ref().map(|session| session.mode)
+    }
 
-    pub fn new() -> Self {
-        let sdl_context = sdl2::init().unwrap();
-        Self {
-            font: Font::new(),
-            video: Video::new(&sdl_context),
-            keyboard: Keyboard::new(&sdl_context),
-            registers: Registers::new(),
-            memory: Memory::new(),
-            stack: Stack::new(),
-            pc: 0,
-            i: 0,
-            delay_timer: 0,
+    // Plays the attached movie to its end without a frontend, checking every state hash.
+    pub fn replay_movie(&mut self) -> Result<(), MovieError> {
+        while self.movie.as_ref().is_some_and(|session| !session.is_finished()) {
+            self.movie_frame()?;
         }
+        Ok(())
     }
 
-    pub fn run(&mut self) {
-        let emulator_step_duration = Duration::new(
-            0, 1_000_000_000u32 / Self::HZ);
+    // Runs one frame, feeding or taking the keypad state of the attached movie.
+    pub fn movie_frame(&mut self) -> Result<(), MovieError> {
+        self.begin_movie_frame();
+        self.run_frame()?;
+        self.end_movie_frame()
+    }
 
-        while self.is_running() {
-            let start = Instant::now();
-            for _ in 0..Self::HZ {
-                self.keyboard.pool();
-                self.exec_opcode(&self.read_opcode());
-                self.video.refresh();
-            }
-            let opcodes_exec_time = Instant::now().duration_since(start);
-            if opcodes_exec_time >= emulator_step_duration {
-                std::thread::sleep(Duration::new(0, 0));
-            } else {
-                let sleep_time = emulator_step_duration - opcodes_exec_time;
-                std::thread::sleep(Duration::new(0, sleep_time.as_nanos() as u32));
+    // Runs frames back to back without a frontend, taking screenshots and recording
+    // as `run` does.
+    pub fn run_headless(&mut self, frames: u64, palette: &Palette) -> Result<(), MovieError> {
+        for frame in 1..=frames {
+            self.movie_frame()?;
+            self.screenshot_frame(frame, palette);
+            self.record_frame(palette);
+        }
+        Ok(())
+    }
+
+    fn begin_movie_frame(&mut self) {
+        if let Some(session) = &mut self.movie {
+            match session.mode {
+                MovieMode::Recording => session.movie.record_keys(session.frame, self.keyboard.mask()),
+                MovieMode::Replaying => self.keyboard.set_mask(session.movie.keys_at(session.frame)),
             }
         }
     }
 
-    pub fn load_rom(&mut self, rom: &Vec<u8>) {
-        let ustart = Self::ROM_START as usize;
-        for (i, e) in rom.iter().enumerate() { self.memory[ustart + i] = *e; }
-        self.pc = ustart as usize;
+    fn end_movie_frame(&mut self) -> Result<(), MovieError> {
+        let hash = match &self.movie {
+            Some(session) if session.is_checkpoint() => self.state_hash(),
+            Some(_) => 0,
+            None => return Ok(()),
+        };
+
+        if let Some(session) = &mut self.movie {
+            if session.is_checkpoint() {
+                match session.mode {
+                    MovieMode::Recording => session.movie.record_hash(session.frame, hash),
+                    MovieMode::Replaying => session.movie.verify_hash(session.frame, hash)?,
+                }
+            }
+            session.frame += 1;
+        }
+        Ok(())
     }
 
-    pub fn load_font(&mut self) {
-        for (i, e) in self.font.memory.iter().enumerate() {
-            self.memory[Font::START as usize + i] = *e;
+    fn write_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.platform.to_u8());
+        writer.u8(self.quirks.to_bits());
+        writer.u32(self.hz);
+        writer.u32(self.pc as u32);
+        writer.u16(self.i);
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        self.registers.write_state(writer);
+        self.stack.write_state(writer);
+        writer.bytes(&self.rpl);
+        writer.bool(self.halted);
+        writer.bool(self.vblank_wait);
+        writer.u8(self.key_wait.unwrap_or(0xFF));
+        writer.bytes(&self.audio_pattern);
+        writer.u8(self.pitch);
+        self.random.write_state(writer);
+        self.keyboard.write_state(writer);
+        self.video.write_state(writer);
+        self.memory.write_state(writer);
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.platform = Platform::from_u8(reader.u8()?).ok_or(StateError::Invalid("platform"))?;
+        self.quirks = Quirks::from_bits(reader.u8()?);
+        self.hz = reader.u32()?;
+        self.pc = reader.u32()? as usize;
+        self.i = reader.u16()?;
+        self.delay_timer = reader.u8()?;
+        self.sound_timer = reader.u8()?;
+        self.registers.read_state(reader)?;
+        self.stack.read_state(reader)?;
+        self.rpl.copy_from_slice(reader.bytes(16)?);
+        self.halted = reader.bool()?;
+        self.vblank_wait = reader.bool()?;
+        self.key_wait = match reader.u8()? {
+            0xFF => None,
+            key => Some(key & 0xF),
+        };
+        self.audio_pattern.copy_from_slice(reader.bytes(16)?);
+        self.pitch = reader.u8()?;
+        self.audio_changed = true;
+        // Older states keep the generator that is already running.
+        if reader.version() >= 2 {
+            self.random.read_state(reader)?;
         }
+        self.keyboard.read_state(reader)?;
+        self.video.read_state(reader)?;
+        self.memory = Memory::with_size(self.platform.memory_size());
+        self.memory.read_state(reader)
     }
 
     pub fn is_running(&self) -> bool {
-        self.pc < self.memory.size() // && self.pc != 0x3dc
+        !self.halted && self.pc < self.memory.size()
+    }
+
+    // Decodes an opcode the way the current platform executes it.
+    pub fn instruction(&self, opcode: Opcode) -> Instruction {
+        let instruction = decode(opcode);
+        if self.platform.supports(&instruction) { return instruction; }
+
+        match opcode.w() {
+            0x0 => Instruction::Sys(opcode.nnn()),
+            _ => Instruction::Invalid(opcode),
+        }
     }
 
-    fn exec_opcode(&mut self, opcode : &Opcode) {
-        match opcode.nibbles() {
-            (0x0, _, _, 0x0) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "CLEAR");
+    pub fn exec_opcode(&mut self, opcode : &Opcode) -> Result<(), CpuError> {
+        match self.instruction(*opcode) {
+            Instruction::Cls => {
                 self.video.clear();
                 self.increment_pc();
             }
-            (0x0, _, _, 0xE) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "self.pc = self.stack.pop() as usize;");
-                self.pc = self.stack.pop() as usize;
+            Instruction::Ret => {
+                self.pc = self.stack.pop()
+                    .ok_or(CpuError::StackUnderflow { pc: self.pc, opcode: *opcode })? as usize;
                 self.increment_pc();
             }
-            (0x1, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "JUMP");
-                self.pc = opcode.nnn() as usize;
-            }
-            (0x2, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "CALL");
-                self.stack.push(self.pc as u16);
-                self.pc = opcode.nnn() as usize;
-            }
-            (0x3, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SE");
-                if self.registers[opcode.x()] == opcode.kk() {
-                    self.increment_pc();
-                }
+            Instruction::Scd(n) => {
+                self.video.scroll_down(n as usize);
                 self.increment_pc();
             }
-            (0x4, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SNE");
-                if self.registers[opcode.x()] != opcode.kk() {
-                    self.increment_pc();
-                }
+            Instruction::Scu(n) => {
+                self.video.scroll_up(n as usize);
                 self.increment_pc();
             }
-            (0x5, _, _, 0x0) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SE");
-                if self.registers[opcode.x()] == self.registers[opcode.y()] {
-                    self.increment_pc();
-                }
+            Instruction::Scr => {
+                self.video.scroll_right(4);
                 self.increment_pc();
             }
-            (0x6, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                self.registers[opcode.x()] = opcode.kk();
+            Instruction::Scl => {
+                self.video.scroll_left(4);
                 self.increment_pc();
             }
-            (0x7, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "ADD");
-                self.registers[opcode.x()] = self.registers[opcode.x()].wrapping_add(opcode.kk());
+            Instruction::Exit => {
+                self.halted = true;
+            }
+            Instruction::Low => {
+                self.video.set_hires(false);
                 self.increment_pc();
             }
-            (0x8, _, _, 0x0) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                self.registers[opcode.x()] = self.registers[opcode.y()];
+            Instruction::High => {
+                self.video.set_hires(true);
                 self.increment_pc();
             }
-            (0x8, _, _, 0x1) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "OR");
-                self.registers[opcode.x()] = self.registers[opcode.x()] | self.registers[opcode.y()];
+            Instruction::Sys(_) => {
                 self.increment_pc();
             }
-            (0x8, _, _, 0x2) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "AND");
-                self.registers[opcode.x()] = self.registers[opcode.x()] & self.registers[opcode.y()];
+            Instruction::Jp(nnn) => {
+                self.pc = nnn as usize;
+            }
+            Instruction::Call(nnn) => {
+                self.stack.push(self.pc as u16)
+                    .ok_or(CpuError::StackOverflow { pc: self.pc, opcode: *opcode })?;
+                self.pc = nnn as usize;
+            }
+            Instruction::SeByte { x, kk } => {
+                self.skip_if(self.registers[x] == kk);
+            }
+            Instruction::SneByte { x, kk } => {
+                self.skip_if(self.registers[x] != kk);
+            }
+            Instruction::SeReg { x, y } => {
+                self.skip_if(self.registers[x] == self.registers[y]);
+            }
+            Instruction::LdByte { x, kk } => {
+                self.registers[x] = kk;
                 self.increment_pc();
             }
-            (0x8, _, _, 0x3) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "XOR");
-                self.registers[opcode.x()] = self.registers[opcode.x()] ^ self.registers[opcode.y()];
+            Instruction::AddByte { x, kk } => {
+                self.registers[x] = self.registers[x].wrapping_add(kk);
                 self.increment_pc();
             }
-            (0x8, _, _, 0x4) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "ADD");
-                if self.registers[opcode.y()] > u8::MAX - self.registers[opcode.x()] {
-                    self.registers[0xF_u16] = 1_u8;
-                } else {
-                    self.registers[0xF_u16] = 0_u8;
-                }
-                self.registers[opcode.x()] = self.registers[opcode.x()].wrapping_add(self.registers[opcode.y()]);
+            Instruction::LdReg { x, y } => {
+                self.registers[x] = self.registers[y];
                 self.increment_pc();
             }
-            (0x8, _, _, 0x5) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SUB");
-                if self.registers[opcode.x()] > self.registers[opcode.y()] {
-                    self.registers[0xF_u16] = 1_u8;
-                } else {
-                    self.registers[0xF_u16] = 0_u8;
-                }
-                self.registers[opcode.x()] = self.registers[opcode.x()].wrapping_sub(self.registers[opcode.y()]);
+            Instruction::Or { x, y } => {
+                self.registers[x] |= self.registers[y];
+                self.reset_vf();
                 self.increment_pc();
             }
-            (0x8, _, _, 0x6) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SHR");
-                self.registers[0xF_u16] = self.registers[opcode.x()] & 0x1;
-                self.registers[opcode.x()] = self.registers[opcode.x()] >> 1;
+            Instruction::And { x, y } => {
+                self.registers[x] &= self.registers[y];
+                self.reset_vf();
                 self.increment_pc();
             }
-            (0x8, _, _, 0x7) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SUBN");
-                if self.registers[opcode.y()] > self.registers[opcode.x()] {
-                    self.registers[0xF_u16] = 1_u8;
-                } else {
-                    self.registers[0xF_u16] = 0_u8;
-                }
+            Instruction::Xor { x, y } => {
+                self.registers[x] ^= self.registers[y];
+                self.reset_vf();
                 self.increment_pc();
-                self.registers[opcode.x()] = self.registers[opcode.y()].wrapping_sub(self.registers[opcode.x()]);
             }
-            (0x8, _, _, 0xE) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SHL");
-                self.registers[0xF_u16] = self.registers[opcode.x()] & 0x80;
-                self.registers[opcode.x()] = self.registers[opcode.x()] << 1;
+            Instruction::AddReg { x, y } => {
+                let (result, carry) = self.registers[x].overflowing_add(self.registers[y]);
+                self.set_with_flag(x, result, carry as u8);
+            }
+            Instruction::Sub { x, y } => {
+                let (result, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
+                self.set_with_flag(x, result, !borrow as u8);
+            }
+            Instruction::Shr { x, y } => {
+                let value = self.shift_source(x, y);
+                self.set_with_flag(x, value >> 1, value & 0x1);
+            }
+            Instruction::Subn { x, y } => {
+                let (result, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
+                self.set_with_flag(x, result, !borrow as u8);
+            }
+            Instruction::Shl { x, y } => {
+                let value = self.shift_source(x, y);
+                self.set_with_flag(x, value << 1, value >> 7);
+            }
+            Instruction::SneReg { x, y } => {
+                self.skip_if(self.registers[x] != self.registers[y]);
+            }
+            Instruction::SaveRange { x, y } => {
+                let count = (x as i8 - y as i8).unsigned_abs() as usize + 1;
+                self.check_memory(opcode, self.i as usize, count)?;
+                for (offset, r) in Self::register_range(x, y).enumerate() {
+                    self.memory[self.i as usize + offset] = self.registers[r];
+                }
                 self.increment_pc();
             }
-            (0x9, _, _, 0x0) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SNE");
-                if self.registers[opcode.x()] != self.registers[opcode.y()] {
-                    self.increment_pc();
+            Instruction::LoadRange { x, y } => {
+                let count = (x as i8 - y as i8).unsigned_abs() as usize + 1;
+                self.check_memory(opcode, self.i as usize, count)?;
+                for (offset, r) in Self::register_range(x, y).enumerate() {
+                    self.registers[r] = self.memory[self.i as usize + offset];
                 }
                 self.increment_pc();
             }
-            (0xA, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                self.i = opcode.nnn();
+            Instruction::LdI(nnn) => {
+                self.i = nnn;
                 self.increment_pc();
             }
-            (0xB, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "JP");
-                self.stack.push(self.pc as u16);
-                self.pc = (opcode.nnn() as u8 + self.registers[0x0_u16]) as usize;
+            Instruction::JpV0(nnn) => {
+                let offset_register = if self.quirks.jump_vx { (nnn >> 8) as u8 } else { 0x0 };
+                self.pc = nnn as usize + self.registers[offset_register] as usize;
             }
-            (0xC, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "RND");
-                let mut rng = rand::thread_rng();
-                self.registers[opcode.x()] = rng.gen_range(0..=255) & opcode.kk();
+            Instruction::Rnd { x, kk } => {
+                self.registers[x] = self.random.next_byte(&self.memory) & kk;
                 self.increment_pc();
             }
-            (0xD, _, _, _) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "DRW");
-                let sprite = self.memory[self.i..(self.i + opcode.n() as u16)].to_vec();
-
-                self.registers[0xF_u16] = self.video.draw_sprite(&sprite,
-                    self.registers[opcode.x()],
-                    self.registers[opcode.y()]);
+            Instruction::Drw { x, y, n } => {
+                let big = n == 0 && self.platform != Platform::Chip8;
+                let len = if big { 32 } else { n as usize } * self.video.plane_count();
+                self.check_memory(opcode, self.i as usize, len)?;
+                let start = self.i as usize;
+                let sprite = self.memory[start..(start + len)].to_vec();
+                let (vx, vy) = (self.registers[x], self.registers[y]);
+
+                self.registers[0xF_u8] = if big {
+                    self.video.draw_big_sprite(&sprite, vx, vy, self.quirks.clip_sprites)
+                } else {
+                    self.video.draw_sprite(&sprite, vx, vy, self.quirks.clip_sprites)
+                };
 
+                self.vblank_wait = self.quirks.display_wait;
                 self.increment_pc();
             }
-            (0xE, _, 0x9, 0xE) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SKP");
-                if self.keyboard.is_key_pressed(self.registers[opcode.x()]) {
-                    self.increment_pc();
-                }
+            Instruction::Skp { x } => {
+                self.skip_if(self.keyboard.is_key_pressed(self.registers[x] & 0xF));
+            }
+            Instruction::Sknp { x } => {
+                self.skip_if(!self.keyboard.is_key_pressed(self.registers[x] & 0xF));
+            }
+            Instruction::LdVxDt { x } => {
+                self.registers[x] = self.delay_timer;
                 self.increment_pc();
             }
-            (0xE, _, 0xA, 0x1) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "SKNP");
-                if !self.keyboard.is_key_pressed(self.registers[opcode.x()]) {
-                    self.increment_pc();
+            Instruction::LdVxK { x } => {
+                match self.key_wait {
+                    Some(key) if !self.keyboard.is_key_pressed(key) => {
+                        self.key_wait = None;
+                        self.registers[x] = key;
+                        self.increment_pc();
+                    }
+                    Some(_) => {}
+                    None => if let Some(key) = self.keyboard.first_pressed_key() {
+                        if self.quirks.key_wait_release {
+                            self.key_wait = Some(key);
+                        } else {
+                            self.registers[x] = key;
+                            self.increment_pc();
+                        }
+                    }
                 }
+            }
+            Instruction::LdDtVx { x } => {
+                self.delay_timer = self.registers[x];
                 self.increment_pc();
             }
-            (0xF, _, 0x0, 0x7) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                self.registers[opcode.x()] = 0_u8;
+            Instruction::LdStVx { x } => {
+                self.sound_timer = self.registers[x];
                 self.increment_pc();
             }
-            (0xF, _, 0x0, 0xA) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                if !self.keyboard.is_any_key_pressed() { return; }
-
-                self.registers[opcode.x()] = self.keyboard.first_pressed_key().unwrap();
+            Instruction::AddI { x } => {
+                self.i = self.i.wrapping_add(self.registers[x] as u16);
                 self.increment_pc();
             }
-            (0xF, _, 0x1, 0x5) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                // TODO
+            Instruction::LdF { x } => {
+                self.i = Font::START + (self.registers[x] & 0xF) as u16 * 5;
                 self.increment_pc();
             }
-            (0xF, _, 0x1, 0x8) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                //  TODO
+            Instruction::LdB { x } => {
+                self.check_memory(opcode, self.i as usize, 3)?;
+                let reg_x = self.registers[x];
+                let i = self.i as usize;
+
+                self.memory[i] = reg_x / 100;
+                self.memory[i + 1] = (reg_x / 10) % 10;
+                self.memory[i + 2] = reg_x % 10;
+
                 self.increment_pc();
             }
-            (0xF, _, 0x1, 0xE) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "ADD");
-                self.i = self.i + self.registers[opcode.x()] as u16;
+            Instruction::LdIVx { x } => {
+                self.check_memory(opcode, self.i as usize, x as usize + 1)?;
+                for r in 0..=x {
+                    self.memory[self.i as usize + r as usize] = self.registers[r];
+                }
+                if self.quirks.load_store_increment_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
                 self.increment_pc();
             }
-            (0xF, _, 0x2, 0x9) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                self.i = Font::START + (self.memory[self.registers[opcode.x()] as usize] * 5) as u16;
+            Instruction::LdVxI { x } => {
+                self.check_memory(opcode, self.i as usize, x as usize + 1)?;
+                for r in 0..=x {
+                    self.registers[r] = self.memory[self.i as usize + r as usize];
+                }
+                if self.quirks.load_store_increment_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
                 self.increment_pc();
             }
-            (0xF, _, 0x3, 0x3) => {
-                print!("{}, op: {:x}, mem: {} ||=> ", self.state(), opcode, "LD");
-                let reg_x = self.registers[opcode.x()];
-
-                print!("reg_x: {}, ", reg_x);
-                print!("BEFORE:, memory[i..i+2] = [{}, {}, {}] => ",
-                    self.memory[self.i],
-                    self.memory[self.i + 1],
-                    self.memory[self.i + 2]);
-
-                self.memory[self.i] = reg_x / 100;
-                self.memory[self.i + 1] = (reg_x / 10) % 10;
-                self.memory[self.i + 2] = reg_x % 10;
-
-                println!("AFTER: memory[i..i+2] = [{}, {}, {}]",
-                     self.memory[self.i],
-                     self.memory[self.i + 1],
-                     self.memory[self.i + 2]);
-
+            Instruction::LdHf { x } => {
+                self.i = Font::BIG_START + (self.registers[x] & 0xF) as u16 * 10;
                 self.increment_pc();
             }
-            (0xF, _, 0x5, 0x5) => {
-                print!("{}, op: {:x}, mem: {} ||=> ", self.state(), opcode, "LD");
-                let x = opcode.x() as usize;
-
-                print!("x: {}, registers[0..=x] = [", x);
-                for (i, e) in self.registers[0..=x].iter().enumerate() {
-                    print!("{} ", *e);
-                    self.memory[self.i + i as u16] = *e;
+            Instruction::LdRVx { x } => {
+                for r in 0..=x {
+                    self.rpl[r as usize] = self.registers[r];
+                }
+                self.increment_pc();
+            }
+            Instruction::LdVxR { x } => {
+                for r in 0..=x {
+                    self.registers[r] = self.rpl[r as usize];
                 }
-                println!("]");
-
                 self.increment_pc();
             }
-            (0xF, _, 0x6, 0x5) => {
-                println!("{}, op: {:x}, mem: {}", self.state(), opcode, "LD");
-                for i in 0..=opcode.x() {
-                    self.registers[i] = self.memory[self.i + i as u16];
+            Instruction::LdILong => {
+                if self.pc + 3 >= self.memory.size() {
+                    return Err(CpuError::PcOutOfRange { pc: self.pc + 2, opcode: Some(*opcode) });
                 }
+                self.i = (self.memory[self.pc + 2] as u16) << 8 | self.memory[self.pc + 3] as u16;
+                self.pc += 4;
+            }
+            Instruction::Plane(n) => {
+                self.video.select_planes(n);
+                self.increment_pc();
+            }
+            Instruction::Audio => {
+                self.check_memory(opcode, self.i as usize, 16)?;
+                let start = self.i as usize;
+                self.audio_pattern.copy_from_slice(&self.memory[start..start + 16]);
+                self.audio_changed = true;
                 self.increment_pc();
             }
-            (_,_,_,_) => {
+            Instruction::Pitch { x } => {
+                self.pitch = self.registers[x];
+                self.audio_changed = true;
                 self.increment_pc();
-                println!("UNKNOWN: {:x}", opcode);
+            }
+            Instruction::Invalid(_) => {
+                return Err(CpuError::InvalidOpcode { pc: self.pc, opcode: *opcode });
             }
         }
+        Ok(())
     }
 
-    fn read_opcode(&self) -> Opcode {
-        let f_nibble = self.memory[self.pc as u16] as u16;
-        let s_nibble = self.memory[self.pc as u16 + 1] as u16;
+    pub fn read_opcode(&self) -> Result<Opcode, CpuError> {
+        if self.pc + 1 >= self.memory.size() {
+            return Err(CpuError::PcOutOfRange { pc: self.pc, opcode: None });
+        }
+
+        let f_nibble = self.memory[self.pc] as u16;
+        let s_nibble = self.memory[self.pc + 1] as u16;
 
-        Opcode::new(f_nibble << 8 | s_nibble)
+        Ok(Opcode::new(f_nibble << 8 | s_nibble))
     }
 
-    fn increment_pc(&mut self) {
-        self.pc = self.pc + 2;
+    fn check_memory(&self, opcode: &Opcode, address: usize, len: usize) -> Result<(), CpuError> {
+        if self.memory.contains(address, len) {
+            Ok(())
+        } else {
+            Err(CpuError::MemoryOutOfRange { pc: self.pc, opcode: *opcode, address: address + len.saturating_sub(1) })
+        }
     }
 
-    pub fn state(&self) -> String {
-        format!("pc: {:#x}, reg: {:x}, stack: {}", self.pc, self.registers, self.stack)
+    fn increment_pc(&mut self) {
+        self.pc += 2;
     }
-}
 
-pub struct Opcode {
-    number: u16,
-}
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.increment_pc();
+            // XO-CHIP skips both words of the long `F000 NNNN` load.
+            if self.platform == Platform::XoChip && self.pc + 1 < self.memory.size()
+                && self.memory[self.pc] == 0xF0 && self.memory[self.pc + 1] == 0x00 {
+                self.increment_pc();
+            }
+        }
+        self.increment_pc();
+    }
 
-impl Opcode {
-    pub fn new(number : u16) -> Self {
-        Self { number }
+    // Registers from Vx to Vy inclusive, in descending order when y < x.
+    fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = u8>> {
+        if x <= y { Box::new(x..=y) } else { Box::new((y..=x).rev()) }
     }
 
-    pub fn nibbles(&self) -> (u8, u8, u8, u8) {
-        (self.w(), self.x(), self.y(), self.n())
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0xF_u8] = 0;
+        }
     }
 
-    pub fn nnn(&self) -> u16 { self.number & 0x0fff }
-    pub fn kk(&self) -> u8 { (self.number & 0x00ff) as u8 }
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_vy { self.registers[y] } else { self.registers[x] }
+    }
 
-    pub fn w(&self) -> u8 { ((self.number & 0xf000) >> 12) as u8 }
-    pub fn x(&self) -> u8 { ((self.number & 0x0f00) >> 8) as u8 }
-    pub fn y(&self) -> u8 { ((self.number & 0x00f0) >> 4) as u8 }
-    pub fn n(&self) -> u8 { (self.number & 0x000f) as u8 }
-}
+    // VF is written last so that flag-producing ops with x == 0xF keep the flag.
+    fn set_with_flag(&mut self, x: u8, value: u8, flag: u8) {
+        self.registers[x] = value;
+        self.registers[0xF_u8] = flag;
+        self.increment_pc();
+    }
 
-impl fmt::Display for Opcode {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "number: {}, nibbles: {:?}", self.number, self.nibbles())
+    // Register state as it appears in trace lines: `v=<16 hex bytes> i=0x22a sp=0`.
+    pub fn state(&self) -> String {
+        let registers: String = self.registers.as_slice().iter().map(|value| format!("{:02x}", value)).collect();
+        format!("v={} i={:#05x} sp={}", registers, self.i, self.stack.pointer())
     }
 }
 
-impl fmt::LowerHex for Opcode {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let nibbles = self.nibbles();
-        write!(fmt, "0x{:x}{:x}{:x}{:x}", nibbles.0, nibbles.1, nibbles.2, nibbles.3)
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
     }
 }
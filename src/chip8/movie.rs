@@ -0,0 +1,214 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::error::CpuError;
+use super::platform::Platform;
+use super::quirks::Quirks;
+use super::random::Random;
+use super::state::{self, StateError, StateReader, StateWriter};
+use super::Emulator;
+
+#[derive(Debug)]
+pub enum MovieError {
+    File(StateError),
+    RomMismatch { expected: u32, actual: u32 },
+    Desync { frame: u64, expected: u32, actual: u32 },
+    Cpu(CpuError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::File(error) => write!(fmt, "{}", error),
+            MovieError::RomMismatch { expected, actual } =>
+                write!(fmt, "movie was recorded with rom {:08x}, loaded rom is {:08x}", expected, actual),
+            MovieError::Desync { frame, expected, actual } =>
+                write!(fmt, "replay desynced at frame {}: state hash {:08x}, expected {:08x}", frame, actual, expected),
+            MovieError::Cpu(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(error: StateError) -> Self {
+        MovieError::File(error)
+    }
+}
+
+impl From<CpuError> for MovieError {
+    fn from(error: CpuError) -> Self {
+        MovieError::Cpu(error)
+    }
+}
+
+// Keypad input of a session recorded from power-on, with the settings needed to
+// reproduce it and state hashes taken every `HASH_INTERVAL` frames.
+#[derive(Clone)]
+pub struct Movie {
+    pub rom_crc: u32,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub random: Random,
+    pub hz: u32,
+    length: u64,
+    // (frame, keypad mask) whenever the keypad changed
+    inputs: Vec<(u64, u16)>,
+    // (frame, crc32 of the state after the frame)
+    hashes: Vec<(u64, u32)>,
+}
+
+impl Movie {
+    pub const MAGIC: &'static [u8; 4] = b"C8MV";
+    pub const VERSION: u16 = 1;
+    pub const HASH_INTERVAL: u64 = 60;
+
+    pub fn new(emulator: &Emulator) -> Self {
+        Self {
+            rom_crc: emulator.rom_crc(),
+            platform: emulator.platform(),
+            quirks: *emulator.quirks(),
+            random: emulator.random().clone(),
+            hz: emulator.hz(),
+            length: 0,
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    // Applies the recorded settings, before the rom is loaded.
+    pub fn configure(&self, emulator: &mut Emulator) {
+        emulator.set_platform(self.platform);
+        emulator.set_quirks(self.quirks);
+        emulator.set_random(self.random.clone());
+        emulator.set_hz(self.hz);
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn set_length(&mut self, frames: u64) {
+        self.length = frames;
+    }
+
+    pub fn inputs(&self) -> &[(u64, u16)] {
+        &self.inputs
+    }
+
+    pub fn hashes(&self) -> &[(u64, u32)] {
+        &self.hashes
+    }
+
+    pub fn keys_at(&self, frame: u64) -> u16 {
+        match self.inputs.binary_search_by_key(&frame, |&(at, _)| at) {
+            Ok(index) => self.inputs[index].1,
+            Err(0) => 0,
+            Err(index) => self.inputs[index - 1].1,
+        }
+    }
+
+    pub fn record_keys(&mut self, frame: u64, keys: u16) {
+        if self.keys_at(frame) != keys {
+            self.inputs.push((frame, keys));
+        }
+    }
+
+    pub fn record_hash(&mut self, frame: u64, hash: u32) {
+        self.hashes.push((frame, hash));
+    }
+
+    pub fn verify_hash(&self, frame: u64, hash: u32) -> Result<(), MovieError> {
+        match self.hashes.binary_search_by_key(&frame, |&(at, _)| at) {
+            Ok(index) if self.hashes[index].1 != hash =>
+                Err(MovieError::Desync { frame, expected: self.hashes[index].1, actual: hash }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.u32(self.rom_crc);
+        writer.u8(self.platform.to_u8());
+        writer.u8(self.quirks.to_bits());
+        self.random.write_state(&mut writer);
+        writer.u32(self.hz);
+        writer.u64(self.length);
+        writer.u32(self.inputs.len() as u32);
+        for &(frame, keys) in self.inputs.iter() {
+            writer.u64(frame);
+            writer.u16(keys);
+        }
+        writer.u32(self.hashes.len() as u32);
+        for &(frame, hash) in self.hashes.iter() {
+            writer.u64(frame);
+            writer.u32(hash);
+        }
+        state::wrap_as(Self::MAGIC, Self::VERSION, &writer.into_inner())
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, StateError> {
+        let (payload, version) = state::unwrap_as(Self::MAGIC, Self::VERSION, data)?;
+        let mut reader = StateReader::new(payload, version);
+
+        let rom_crc = reader.u32()?;
+        let platform = Platform::from_u8(reader.u8()?).ok_or(StateError::Invalid("platform"))?;
+        let quirks = Quirks::from_bits(reader.u8()?);
+        let mut random = Random::default();
+        random.read_state(&mut reader)?;
+        let hz = reader.u32()?;
+        let length = reader.u64()?;
+
+        let mut inputs = Vec::new();
+        for _ in 0..reader.u32()? {
+            inputs.push((reader.u64()?, reader.u16()?));
+        }
+        let mut hashes = Vec::new();
+        for _ in 0..reader.u32()? {
+            hashes.push((reader.u64()?, reader.u32()?));
+        }
+        if !reader.is_empty() { return Err(StateError::Invalid("trailing data")); }
+        if inputs.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+            || hashes.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(StateError::Invalid("frame order"));
+        }
+
+        Ok(Self { rom_crc, platform, quirks, random, hz, length, inputs, hashes })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), StateError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, StateError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MovieMode {
+    Recording,
+    Replaying,
+}
+
+// A movie attached to a running emulator along with the frame it is at.
+#[derive(Clone)]
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    pub frame: u64,
+}
+
+impl MovieSession {
+    pub fn is_finished(&self) -> bool {
+        self.mode == MovieMode::Replaying && self.frame >= self.movie.length()
+    }
+
+    pub fn is_checkpoint(&self) -> bool {
+        self.frame.is_multiple_of(Movie::HASH_INTERVAL)
+    }
+}
@@ -0,0 +1,39 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Opcode {
+    number: u16,
+}
+
+impl Opcode {
+    pub fn new(number : u16) -> Self {
+        Self { number }
+    }
+
+    pub fn number(&self) -> u16 { self.number }
+
+    pub fn nibbles(&self) -> (u8, u8, u8, u8) {
+        (self.w(), self.x(), self.y(), self.n())
+    }
+
+    pub fn nnn(&self) -> u16 { self.number & 0x0fff }
+    pub fn kk(&self) -> u8 { (self.number & 0x00ff) as u8 }
+
+    pub fn w(&self) -> u8 { ((self.number & 0xf000) >> 12) as u8 }
+    pub fn x(&self) -> u8 { ((self.number & 0x0f00) >> 8) as u8 }
+    pub fn y(&self) -> u8 { ((self.number & 0x00f0) >> 4) as u8 }
+    pub fn n(&self) -> u8 { (self.number & 0x000f) as u8 }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "number: {}, nibbles: {:?}", self.number, self.nibbles())
+    }
+}
+
+impl fmt::LowerHex for Opcode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let nibbles = self.nibbles();
+        write!(fmt, "0x{:x}{:x}{:x}{:x}", nibbles.0, nibbles.1, nibbles.2, nibbles.3)
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Colors indexed by the value of the two display planes: bit 0 is plane 1 and
+// bit 1 is plane 2, so classic single-plane programs only use colors 0 and 1.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+    pub colors: [(u8, u8, u8); 4],
+}
+
+impl Palette {
+    pub const PRESETS: [&'static str; 4] = ["classic", "lcd-green", "amber", "high-contrast"];
+    // Names of colors 0 to 3; `bg` and `fg` are accepted for the first two.
+    pub const NAMES: [&'static str; 4] = ["background", "foreground", "plane2", "overlap"];
+
+    pub fn new() -> Self {
+        Self::classic()
+    }
+
+    // Lit pixels in white on black.
+    pub fn classic() -> Self {
+        Self {
+            colors: [
+                (0, 0, 0),
+                (255, 255, 255),
+                (170, 170, 170),
+                (85, 85, 85),
+            ],
+        }
+    }
+
+    // Dark pixels on a green liquid crystal screen.
+    pub fn lcd_green() -> Self {
+        Self {
+            colors: [
+                (0x9b, 0xbc, 0x0f),
+                (0x0f, 0x38, 0x0f),
+                (0x30, 0x62, 0x30),
+                (0x8b, 0xac, 0x0f),
+            ],
+        }
+    }
+
+    // Octo's default colors.
+    pub fn amber() -> Self {
+        Self {
+            colors: [
+                (0x99, 0x66, 0x00),
+                (0xff, 0xcc, 0x00),
+                (0xff, 0x66, 0x00),
+                (0x66, 0x22, 0x00),
+            ],
+        }
+    }
+
+    // Saturated colors that keep the two planes and their overlap apart.
+    pub fn high_contrast() -> Self {
+        Self {
+            colors: [
+                (0, 0, 0),
+                (255, 255, 255),
+                (255, 255, 0),
+                (0, 255, 255),
+            ],
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "lcd-green" => Some(Self::lcd_green()),
+            "amber" => Some(Self::amber()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    pub fn color(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[(index & 0x3) as usize]
+    }
+
+    pub fn set(&mut self, name: &str, color: (u8, u8, u8)) -> Result<(), String> {
+        let index = match name {
+            "bg" => 0,
+            "fg" => 1,
+            _ => Self::NAMES.iter().position(|&known| known == name)
+                .ok_or_else(|| format!("unknown color: {}", name))?,
+        };
+        self.colors[index] = color;
+        Ok(())
+    }
+
+    // Applies an override written as `name=rrggbb`, with an optional `#`.
+    pub fn apply_override(&mut self, assignment: &str) -> Result<(), String> {
+        let mut parts = assignment.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let color = parts.next()
+            .and_then(|value| parse_color(value.trim()))
+            .ok_or_else(|| format!("expected {}=rrggbb", name))?;
+        self.set(name, color)
+    }
+
+    // A palette file has one `name=rrggbb` override per line, blank lines and `#`
+    // comments ignored. A `preset=name` line starts over from that preset.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut palette = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let result = match line.strip_prefix("preset=") {
+                Some(name) => Self::preset(name.trim())
+                    .map(|preset| palette = preset)
+                    .ok_or_else(|| format!("unknown palette preset: {}", name.trim())),
+                None => palette.apply_override(line),
+            };
+            result.map_err(|error| format!("palette line {}: {}", index + 1, error))?;
+        }
+        Ok(palette)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let digits = text.strip_prefix('#').or_else(|| text.strip_prefix("0x")).unwrap_or(text);
+    if digits.len() != 6 { return None; }
+
+    let rgb = u32::from_str_radix(digits, 16).ok()?;
+    Some(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+use super::memory::Memory;
+use super::quirks::Quirks;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const NAMES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => 0x10000,
+            _ => Memory::DEFAULT_SIZE,
+        }
+    }
+
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::schip(),
+            Platform::XoChip => Quirks::modern(),
+        }
+    }
+
+    pub fn supports(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Scd(_) | Instruction::Scr | Instruction::Scl
+            | Instruction::Exit | Instruction::Low | Instruction::High
+            | Instruction::LdHf { .. } | Instruction::LdRVx { .. }
+            | Instruction::LdVxR { .. } => *self != Platform::Chip8,
+            Instruction::Scu(_) | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. } | Instruction::LdILong
+            | Instruction::Plane(_) | Instruction::Audio
+            | Instruction::Pitch { .. } => *self == Platform::XoChip,
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform: {}", name)),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place.
+    pub shift_vy: bool,
+    // Fx55/Fx65 leave I pointing past the last register stored or loaded.
+    pub load_store_increment_i: bool,
+    // Bxnn jumps to xnn + Vx instead of nnn + V0.
+    pub jump_vx: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    // Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    // DRW waits for the next 60 Hz frame before execution continues.
+    pub display_wait: bool,
+    // Fx0A completes when the key is released rather than when it is pressed.
+    pub key_wait_release: bool,
+}
+
+impl Quirks {
+    pub const PRESETS: [&'static str; 4] = ["vip", "chip48", "schip", "modern"];
+    pub const NAMES: [&'static str; 7] = [
+        "shift-vy", "load-store-increment-i", "jump-vx", "vf-reset",
+        "clip-sprites", "display-wait", "key-wait-release",
+    ];
+
+    pub fn vip() -> Self {
+        Self {
+            shift_vy: true,
+            load_store_increment_i: true,
+            jump_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+            key_wait_release: true,
+        }
+    }
+
+    pub fn chip48() -> Self {
+        Self {
+            shift_vy: false,
+            load_store_increment_i: false,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    pub fn schip() -> Self {
+        Self {
+            shift_vy: false,
+            load_store_increment_i: false,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+
+    pub fn modern() -> Self {
+        Self {
+            shift_vy: true,
+            load_store_increment_i: true,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "vip" => Some(Self::vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip" => Some(Self::schip()),
+            "modern" => Some(Self::modern()),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match name {
+            "shift-vy" => self.shift_vy = value,
+            "load-store-increment-i" => self.load_store_increment_i = value,
+            "jump-vx" => self.jump_vx = value,
+            "vf-reset" => self.vf_reset = value,
+            "clip-sprites" => self.clip_sprites = value,
+            "display-wait" => self.display_wait = value,
+            "key-wait-release" => self.key_wait_release = value,
+            _ => return Err(format!("unknown quirk: {}", name)),
+        }
+        Ok(())
+    }
+
+    pub fn to_bits(&self) -> u8 {
+        [self.shift_vy, self.load_store_increment_i, self.jump_vx, self.vf_reset,
+         self.clip_sprites, self.display_wait, self.key_wait_release]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (index, &flag)| bits | (flag as u8) << index)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let flag = |index: u8| bits & (1 << index) != 0;
+        Self {
+            shift_vy: flag(0),
+            load_store_increment_i: flag(1),
+            jump_vx: flag(2),
+            vf_reset: flag(3),
+            clip_sprites: flag(4),
+            display_wait: flag(5),
+            key_wait_release: flag(6),
+        }
+    }
+
+    // Applies an override written as `name=on` or `name=off`.
+    pub fn apply_override(&mut self, assignment: &str) -> Result<(), String> {
+        let mut parts = assignment.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = match parts.next() {
+            Some("on") | Some("true") | Some("1") => true,
+            Some("off") | Some("false") | Some("0") => false,
+            _ => return Err(format!("expected {}=on|off", name)),
+        };
+        self.set(name, value)
+    }
+}
+
+// The behaviour from before quirks could be chosen: shifts work on Vx in place, I is
+// left alone by Fx55/Fx65, Bnnn adds V0, sprites clip and nothing waits for vblank.
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_vy: false,
+            load_store_increment_i: false,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+}
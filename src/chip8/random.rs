@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use super::memory::Memory;
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RandomMode {
+    #[default]
+    Xorshift,
+    Vip,
+}
+
+impl RandomMode {
+    pub const NAMES: [&'static str; 2] = ["xorshift", "vip"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RandomMode::Xorshift => "xorshift",
+            RandomMode::Vip => "vip",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RandomMode::Xorshift),
+            1 => Some(RandomMode::Vip),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for RandomMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "xorshift" => Ok(RandomMode::Xorshift),
+            "vip" => Ok(RandomMode::Vip),
+            _ => Err(format!("unknown random mode: {}", name)),
+        }
+    }
+}
+
+// Random source for Cxkk, owned by the emulator so runs can be reproduced from a seed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Random {
+    mode: RandomMode,
+    seed: u64,
+    state: u64,
+}
+
+impl Random {
+    pub fn new(mode: RandomMode, seed: u64) -> Self {
+        let mut random = Self { mode, seed, state: 0 };
+        random.reseed(seed);
+        random
+    }
+
+    pub fn mode(&self) -> RandomMode {
+        self.mode
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_mode(&mut self, mode: RandomMode) {
+        self.mode = mode;
+        self.reseed(self.seed);
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.state = match self.mode {
+            RandomMode::Xorshift => Self::splitmix(seed),
+            RandomMode::Vip => seed & 0xFFFF,
+        };
+    }
+
+    // The VIP interpreter keeps its seed in R9: every call steps it, adds the byte found at
+    // 0x100 + R9.0 (the interpreter's own code page on a real machine) into R9.1 and returns R9.1.
+    pub fn next_byte(&mut self, memory: &Memory) -> u8 {
+        match self.mode {
+            RandomMode::Xorshift => {
+                let mut x = self.state;
+                x ^= x >> 12;
+                x ^= x << 25;
+                x ^= x >> 27;
+                self.state = x;
+                (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            RandomMode::Vip => {
+                let r9 = (self.state as u16).wrapping_add(1);
+                let [low, high] = r9.to_le_bytes();
+                let high = high.wrapping_add(memory[0x100 + low as usize]);
+                self.state = u16::from_le_bytes([low, high]) as u64;
+                high
+            }
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.mode.to_u8());
+        writer.u64(self.seed);
+        writer.u64(self.state);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.mode = RandomMode::from_u8(reader.u8()?).ok_or(StateError::Invalid("random mode"))?;
+        self.seed = reader.u64()?;
+        self.state = reader.u64()?;
+        if self.mode == RandomMode::Xorshift && self.state == 0 {
+            return Err(StateError::Invalid("random state"));
+        }
+        Ok(())
+    }
+
+    // Spreads the seed over the whole state; xorshift must never start from zero.
+    fn splitmix(seed: u64) -> u64 {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        if z == 0 { 1 } else { z }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(RandomMode::default(), 0)
+    }
+}
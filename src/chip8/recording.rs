@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::palette::Palette;
+use super::screenshot::{write_chunk, Image, PNG_SIGNATURE};
+use super::video::Video;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat {
+    Gif,
+    Apng,
+    // Headerless rgb24 frames at 60 per second, for piping into an encoder.
+    Raw,
+}
+
+impl RecordingFormat {
+    pub const NAMES: [&'static str; 3] = ["gif", "apng", "raw"];
+
+    // `-` is the raw pipe to stdout, a `.gif` extension is a gif and anything else an apng.
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str());
+        match extension {
+            _ if path == "-" => RecordingFormat::Raw,
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => RecordingFormat::Gif,
+            _ => RecordingFormat::Apng,
+        }
+    }
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "gif" => Ok(RecordingFormat::Gif),
+            "apng" => Ok(RecordingFormat::Apng),
+            "raw" => Ok(RecordingFormat::Raw),
+            _ => Err(format!("unknown recording format: {}", name)),
+        }
+    }
+}
+
+enum Output {
+    File(BufWriter<File>),
+    Stdout(io::Stdout),
+}
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::File(file) => file.write(data),
+            Output::Stdout(stdout) => stdout.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::File(file) => file.flush(),
+            Output::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+// Captures the display once per 60 Hz frame. The size is fixed by the first frame.
+// Gif and apng hold a frame back until it changes, so a still screen is stored once
+// with a longer delay.
+pub struct Recorder {
+    format: RecordingFormat,
+    out: Output,
+    scale: u32,
+    size: Option<(usize, usize)>,
+    pending: Option<(Image, u32)>,
+    frames: u64,
+    // Frames written to the file, the captures they stand for and, for gif, the time
+    // they cover in centiseconds.
+    written: u32,
+    shown: u64,
+    centiseconds: u64,
+}
+
+impl Recorder {
+    pub const FPS: u32 = 60;
+    // Keeps a gif delay within 16 bits of centiseconds.
+    const MAX_REPEATS: u32 = 30_000;
+
+    pub fn create(path: &str, format: RecordingFormat, scale: u32) -> io::Result<Self> {
+        let out = match format {
+            RecordingFormat::Raw if path == "-" => Output::Stdout(io::stdout()),
+            _ => Output::File(BufWriter::new(File::create(path)?)),
+        };
+        Ok(Self {
+            format,
+            out,
+            scale: scale.max(1),
+            size: None,
+            pending: None,
+            frames: 0,
+            written: 0,
+            shown: 0,
+            centiseconds: 0,
+        })
+    }
+
+    pub fn format(&self) -> RecordingFormat {
+        self.format
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Width and height of the recording once the first frame is in.
+    pub fn size(&self) -> Option<(usize, usize)> {
+        self.size
+    }
+
+    pub fn frame(&mut self, video: &Video, palette: &Palette) -> io::Result<()> {
+        let scale = self.scale as usize;
+        let (width, height) = *self.size.get_or_insert((video.width() * scale, video.height() * scale));
+        let image = Image::capture_size(video, palette, width, height);
+
+        if self.frames == 0 {
+            self.write_header(&image)?;
+        }
+        self.frames += 1;
+
+        if self.format == RecordingFormat::Raw {
+            return self.out.write_all(&image.rgb());
+        }
+        match &mut self.pending {
+            Some((pending, repeats)) if *pending == image && *repeats < Self::MAX_REPEATS => *repeats += 1,
+            _ => {
+                if let Some((pending, repeats)) = self.pending.take() {
+                    self.write_frame(&pending, repeats)?;
+                }
+                self.pending = Some((image, 1));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some((pending, repeats)) = self.pending.take() {
+            self.write_frame(&pending, repeats)?;
+        }
+        match self.format {
+            RecordingFormat::Gif if self.frames > 0 => self.out.write_all(&[0x3b])?,
+            RecordingFormat::Apng if self.frames > 0 => {
+                write_chunk(&mut self.out, b"IEND", &[])?;
+                self.patch_frame_count()?;
+            }
+            _ => {}
+        }
+        self.out.flush()
+    }
+
+    fn write_header(&mut self, image: &Image) -> io::Result<()> {
+        let out = &mut self.out;
+        match self.format {
+            RecordingFormat::Gif => {
+                out.write_all(b"GIF89a")?;
+                out.write_all(&(image.width() as u16).to_le_bytes())?;
+                out.write_all(&(image.height() as u16).to_le_bytes())?;
+                // A global table of four colors, two bits per entry.
+                out.write_all(&[0x91, 0, 0])?;
+                out.write_all(&image.png_palette())?;
+                // Loops forever.
+                out.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")
+            }
+            RecordingFormat::Apng => {
+                out.write_all(&PNG_SIGNATURE)?;
+                write_chunk(out, b"IHDR", &image.png_header())?;
+                write_chunk(out, b"PLTE", &image.png_palette())?;
+                write_chunk(out, b"acTL", &animation_control(0))
+            }
+            RecordingFormat::Raw => {
+                eprintln!(
+                    "recording rgb24 {}x{} at {} fps, e.g. ffmpeg -f rawvideo -pixel_format rgb24 -video_size {}x{} -framerate {} -i - out.mp4",
+                    image.width(), image.height(), Self::FPS, image.width(), image.height(), Self::FPS
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn write_frame(&mut self, image: &Image, repeats: u32) -> io::Result<()> {
+        match self.format {
+            RecordingFormat::Gif => self.write_gif_frame(image, repeats)?,
+            RecordingFormat::Apng => self.write_apng_frame(image, repeats)?,
+            RecordingFormat::Raw => {}
+        }
+        self.written += 1;
+        self.shown += repeats as u64;
+        Ok(())
+    }
+
+    // Gif delays are whole centiseconds, so they are rounded against the running total
+    // to keep the overall speed right. Most viewers treat delays under 2 as slow.
+    fn write_gif_frame(&mut self, image: &Image, repeats: u32) -> io::Result<()> {
+        let end = (self.shown + repeats as u64) * 100 / Self::FPS as u64;
+        let delay = end.saturating_sub(self.centiseconds).max(2);
+        self.centiseconds += delay;
+
+        let out = &mut self.out;
+        out.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+        out.write_all(&(delay as u16).to_le_bytes())?;
+        out.write_all(&[0x00, 0x00])?;
+
+        out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        out.write_all(&(image.width() as u16).to_le_bytes())?;
+        out.write_all(&(image.height() as u16).to_le_bytes())?;
+        out.write_all(&[0x00, 2])?;
+        for block in lzw_encode(image.pixels(), 2).chunks(255) {
+            out.write_all(&[block.len() as u8])?;
+            out.write_all(block)?;
+        }
+        out.write_all(&[0])
+    }
+
+    // Every frame is a full image with a delay of `repeats / 60` seconds.
+    fn write_apng_frame(&mut self, image: &Image, repeats: u32) -> io::Result<()> {
+        // fcTL and fdAT share one sequence; the first frame's IDAT has no number.
+        let sequence = (self.written * 2).saturating_sub(1);
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&sequence.to_be_bytes());
+        control.extend_from_slice(&(image.width() as u32).to_be_bytes());
+        control.extend_from_slice(&(image.height() as u32).to_be_bytes());
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&(repeats as u16).to_be_bytes());
+        control.extend_from_slice(&(Self::FPS as u16).to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut self.out, b"fcTL", &control)?;
+
+        if self.written == 0 {
+            write_chunk(&mut self.out, b"IDAT", &image.png_data())
+        } else {
+            let mut data = (sequence + 1).to_be_bytes().to_vec();
+            data.extend_from_slice(&image.png_data());
+            write_chunk(&mut self.out, b"fdAT", &data)
+        }
+    }
+
+    // The frame count in acTL is only known at the end.
+    fn patch_frame_count(&mut self) -> io::Result<()> {
+        if let Output::File(file) = &mut self.out {
+            let mut chunk = Vec::new();
+            write_chunk(&mut chunk, b"acTL", &animation_control(self.written))?;
+            file.flush()?;
+            let file = file.get_mut();
+            // Signature, IHDR and the four color PLTE come first.
+            file.seek(SeekFrom::Start(8 + 25 + 24))?;
+            file.write_all(&chunk)?;
+            file.seek(SeekFrom::End(0))?;
+        }
+        Ok(())
+    }
+}
+
+// Frame count, then zero plays for looping forever.
+fn animation_control(frames: u32) -> Vec<u8> {
+    let mut control = frames.to_be_bytes().to_vec();
+    control.extend_from_slice(&0u32.to_be_bytes());
+    control
+}
+
+// Variable width gif lzw, packing codes least significant bit first.
+fn lzw_encode(pixels: &[u8], min_size: u32) -> Vec<u8> {
+    const MAX_CODE: u16 = 4096;
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+
+    let mut out = Vec::new();
+    let (mut bits, mut count) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32| {
+        bits |= (code as u32) << count;
+        count += size;
+        while count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_size + 1;
+    let mut next = end + 1;
+    emit(clear, size);
+
+    let mut pixels = pixels.iter();
+    if let Some(&first) = pixels.next() {
+        let mut prefix = first as u16;
+        for &pixel in pixels {
+            if let Some(&code) = codes.get(&(prefix, pixel)) {
+                prefix = code;
+                continue;
+            }
+            emit(prefix, size);
+            if next == MAX_CODE {
+                emit(clear, size);
+                codes.clear();
+                size = min_size + 1;
+                next = end + 1;
+            } else {
+                // The decoder widens its codes once its table reaches the next power of two.
+                if next >= 1 << size && size < 12 { size += 1; }
+                codes.insert((prefix, pixel), next);
+                next += 1;
+            }
+            prefix = pixel as u16;
+        }
+        emit(prefix, size);
+        if next >= 1 << size && size < 12 { size += 1; }
+    }
+    emit(end, size);
+    if count > 0 { out.push(bits as u8); }
+    out
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::ops::{Index, IndexMut, RangeTo, Range, RangeInclusive};
+
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone)]
+pub struct Registers([u8; 16]);
+impl Registers {
+    pub fn new() -> Self {
+        Self([0; 16])
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.0);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.0.copy_from_slice(reader.bytes(16)?);
+        Ok(())
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<u8> for Registers {
+    type Output = u8;
+
+    fn index(&self, i: u8) -> &Self::Output {
+        &self.0[i as usize]
+    }
+}
+
+impl Index<u16> for Registers {
+    type Output = u8;
+
+    fn index(&self, i: u16) -> &Self::Output {
+        &self.0[i as usize]
+    }
+}
+
+impl IndexMut<u16> for Registers {
+    fn index_mut(&mut self, i: u16) -> &mut Self::Output {
+        &mut self.0[i as usize]
+    }
+}
+
+impl IndexMut<u8> for Registers {
+    fn index_mut(&mut self, i: u8) -> &mut Self::Output {
+        &mut self.0[i as usize]
+    }
+}
+
+impl Index<Range<usize>> for Registers {
+    type Output = [u8];
+
+    fn index(&self, index: Range<usize>) -> &[u8] {
+        &self.0[..][index]
+    }
+}
+
+impl Index<RangeInclusive<usize>> for Registers {
+    type Output = [u8];
+
+    fn index(&self, index: RangeInclusive<usize>) -> &[u8] {
+        &self.0[..][index]
+    }
+}
+
+impl Index<RangeTo<usize>> for Registers {
+    type Output = [u8];
+
+    fn index(&self, index: RangeTo<usize>) -> &[u8] {
+        &self.0[..][index]
+    }
+}
+
+impl fmt::LowerHex for Registers {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "[")?;
+        for e in self.0.iter() { write!(fmt, "{:x}, ", *e)?; }
+        write!(fmt, "]")
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use super::debug::{disassemble, set_register, DebugClient, Debugger, Resume, StopReason};
+use super::Emulator;
+
+const HELP: &str = "\
+numbers are hex, prefix with # for decimal
+  s, step [n]          execute n instructions
+  n, next              step over a 2nnn call
+  c, continue          run until a breakpoint or watchpoint
+  p, pause             stop a running program
+  b, break [addr]      set a breakpoint, list them without an address
+  d, delete addr       remove a breakpoint
+  w, watch addr [len]  stop when memory changes, list them without an address
+  u, unwatch addr      remove a watchpoint
+  r, regs              show registers, I, PC, SP and timers
+  x addr [len]         dump memory
+  l, list [addr] [n]   disassemble around PC or from addr
+  stack                show the stack
+  set reg value        edit V0-VF, I, PC, SP, DT or ST
+  poke addr byte...    edit memory
+  q, quit              exit the emulator";
+
+// Terminal debugger reading commands (usually stdin) on its own thread, so the
+// emulator window keeps running while it waits for input.
+pub struct Repl {
+    lines: Receiver<String>,
+    pending_steps: Option<u32>,
+    last_command: String,
+}
+
+impl Repl {
+    pub fn new<R: Read + Send + 'static>(input: R) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(input).lines() {
+                let line = match line { Ok(line) => line, Err(_) => break };
+                if sender.send(line).is_err() { break; }
+            }
+        });
+
+        eprintln!("debugger ready, type help for commands");
+        Self::prompt();
+        Self { lines, pending_steps: None, last_command: String::new() }
+    }
+
+    fn prompt() {
+        print!("(chip8) ");
+        let _ = io::stdout().flush();
+    }
+
+    fn execute(&mut self, line: &str, debugger: &mut Debugger, emulator: &mut Emulator) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(true),
+        };
+
+        match command {
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(false),
+            "s" | "step" => {
+                let count = args.first().map(|arg| parse(arg)).transpose()?.unwrap_or(1).max(1);
+                self.pending_steps = Some((count - 1).min(u32::MAX as usize) as u32);
+                debugger.resume(emulator, Resume::Step);
+            }
+            "n" | "next" => debugger.resume(emulator, Resume::StepOver),
+            "c" | "continue" => debugger.resume(emulator, Resume::Continue),
+            "p" | "pause" => {
+                debugger.pause();
+                self.print_location(emulator);
+            }
+            "b" | "break" => match args.first() {
+                Some(arg) => { debugger.add_breakpoint(parse(arg)?); }
+                None => for address in debugger.breakpoints() { println!("breakpoint {:#05x}", address) },
+            },
+            "d" | "delete" => {
+                let address = parse(args.first().ok_or("missing address")?)?;
+                if !debugger.remove_breakpoint(address) { return Err(format!("no breakpoint at {:#05x}", address)); }
+            }
+            "w" | "watch" => match args.first() {
+                Some(arg) => {
+                    let address = parse(arg)?;
+                    let len = args.get(1).map(|arg| parse(arg)).transpose()?.unwrap_or(1);
+                    if !emulator.memory().contains(address, len) { return Err("address out of range".to_string()); }
+                    for address in address..address + len { debugger.add_watchpoint(address); }
+                }
+                None => for address in debugger.watchpoints() { println!("watchpoint {:#05x}", address) },
+            },
+            "u" | "unwatch" => {
+                let address = parse(args.first().ok_or("missing address")?)?;
+                if !debugger.remove_watchpoint(address) { return Err(format!("no watchpoint at {:#05x}", address)); }
+            }
+            "r" | "regs" => Self::print_registers(emulator),
+            "x" => {
+                let address = parse(args.first().ok_or("missing address")?)?;
+                let len = args.get(1).map(|arg| parse(arg)).transpose()?.unwrap_or(16);
+                Self::print_memory(emulator, address, len);
+            }
+            "l" | "list" => {
+                let count = args.get(1).map(|arg| parse(arg)).transpose()?.unwrap_or(10);
+                let start = match args.first() {
+                    Some(arg) => parse(arg)?,
+                    None => emulator.pc().saturating_sub(8),
+                };
+                Self::print_disassembly(emulator, start, count);
+            }
+            "stack" => {
+                let stack = emulator.stack();
+                println!("SP {}", stack.pointer());
+                for (index, value) in stack.values().iter().enumerate().rev() {
+                    println!("  [{:x}] {:#05x}", index, value);
+                }
+            }
+            "set" => {
+                let (name, value) = match args {
+                    [name, value] => (name.to_ascii_lowercase(), parse(value)?),
+                    _ => return Err("usage: set reg value".to_string()),
+                };
+                set_register(emulator, &name, value)?;
+            }
+            "poke" => {
+                let address = parse(args.first().ok_or("missing address")?)?;
+                let bytes = args[1..].iter().map(|arg| parse(arg)).collect::<Result<Vec<_>, _>>()?;
+                if !emulator.memory().contains(address, bytes.len()) { return Err("address out of range".to_string()); }
+                let memory = emulator.memory_mut().as_mut_slice();
+                for (offset, byte) in bytes.iter().enumerate() {
+                    memory[address + offset] = *byte as u8;
+                }
+            }
+            _ => return Err(format!("unknown command {}, try help", command)),
+        }
+        Ok(true)
+    }
+
+    fn print_registers(emulator: &Emulator) {
+        let registers = emulator.registers().as_slice();
+        for (row, chunk) in registers.chunks(8).enumerate() {
+            let line: Vec<String> = chunk.iter().enumerate()
+                .map(|(index, value)| format!("V{:X}={:02x}", row * 8 + index, value))
+                .collect();
+            println!("{}", line.join(" "));
+        }
+        println!(
+            "I={:03x} PC={:03x} SP={} DT={:02x} ST={:02x}",
+            emulator.i(), emulator.pc(), emulator.stack().pointer(), emulator.delay_timer(), emulator.sound_timer()
+        );
+    }
+
+    fn print_memory(emulator: &Emulator, address: usize, len: usize) {
+        let memory = emulator.memory().as_slice();
+        let end = address.saturating_add(len).min(memory.len());
+        for start in (address..end).step_by(16) {
+            let bytes: Vec<String> = memory[start..(start + 16).min(end)].iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            println!("{:04x}: {}", start, bytes.join(" "));
+        }
+    }
+
+    fn print_disassembly(emulator: &Emulator, start: usize, count: usize) {
+        let mut address = start;
+        for _ in 0..count {
+            let (opcode, instruction, len) = match disassemble(emulator, address) {
+                Some(decoded) => decoded,
+                None => break,
+            };
+            let marker = if address == emulator.pc() { "=>" } else { "  " };
+            println!("{} {:04x}: {:04x}  {}", marker, address, opcode.number(), instruction);
+            address += len;
+        }
+    }
+
+    fn print_location(&self, emulator: &Emulator) {
+        Self::print_disassembly(emulator, emulator.pc(), 1);
+    }
+}
+
+impl DebugClient for Repl {
+    fn poll(&mut self, debugger: &mut Debugger, emulator: &mut Emulator) -> bool {
+        if self.pending_steps.is_some() { return true; }
+
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return debugger.is_paused() || emulator.is_running(),
+            };
+
+            // An empty line repeats the last command, like gdb.
+            let line = if line.trim().is_empty() { self.last_command.clone() } else { line };
+            self.last_command = line.clone();
+
+            match self.execute(&line, debugger, emulator) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(error) => println!("{}", error),
+            }
+            // Let the emulator run before reading further commands.
+            if !debugger.is_paused() { return true; }
+            Self::prompt();
+        }
+    }
+
+    fn stopped(&mut self, debugger: &mut Debugger, emulator: &mut Emulator, reason: StopReason) {
+        if let (StopReason::Step, Some(remaining)) = (reason, self.pending_steps) {
+            if remaining > 0 {
+                self.pending_steps = Some(remaining - 1);
+                debugger.resume(emulator, Resume::Step);
+                return;
+            }
+        }
+        self.pending_steps = None;
+
+        match reason {
+            StopReason::Pause | StopReason::Step => {}
+            StopReason::Breakpoint(address) => println!("breakpoint at {:#05x}", address),
+            StopReason::Watchpoint { address, old, new } =>
+                println!("watchpoint {:#05x}: {:02x} -> {:02x}", address, old, new),
+            StopReason::Exit => println!("program exited"),
+            StopReason::Fault(error) => println!("{}", error),
+        }
+        self.print_location(emulator);
+        Self::prompt();
+    }
+}
+
+// Hex by default, `#` for decimal; an optional 0x prefix is accepted.
+fn parse(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix('#') {
+        Some(decimal) => decimal.parse(),
+        None => usize::from_str_radix(text.trim_start_matches("0x"), 16),
+    };
+    parsed.map_err(|_| format!("invalid number {}", text))
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use super::state::StateError;
+use super::Emulator;
+
+// Bytes of an older snapshot that differ from the next newer one.
+struct Delta {
+    len: usize,
+    runs: Vec<(usize, Vec<u8>)>,
+}
+
+impl Delta {
+    // Runs separated by fewer unchanged bytes than this are merged into one.
+    const MERGE_GAP: usize = 8;
+
+    fn between(older: &[u8], newer: &[u8]) -> Self {
+        if older.len() != newer.len() {
+            return Self { len: older.len(), runs: vec![(0, older.to_vec())] };
+        }
+
+        let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut index = 0;
+        while index < older.len() {
+            if older[index] == newer[index] {
+                index += 1;
+                continue;
+            }
+
+            let start = index;
+            let mut end = index + 1;
+            while older[end..].iter().zip(&newer[end..]).take(Self::MERGE_GAP).any(|(a, b)| a != b) {
+                end += 1;
+            }
+            runs.push((start, older[start..end].to_vec()));
+            index = end;
+        }
+        Self { len: older.len(), runs }
+    }
+
+    fn apply(&self, snapshot: &mut Vec<u8>) {
+        snapshot.resize(self.len, 0);
+        for (start, bytes) in self.runs.iter() {
+            snapshot[*start..*start + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.runs.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+}
+
+// Rolling history of per-frame snapshots. Only the newest snapshot is kept whole,
+// every older frame is stored as the delta needed to step back to it.
+pub struct Rewind {
+    history: VecDeque<Delta>,
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+            newest: None,
+        }
+    }
+
+    pub fn record(&mut self, emulator: &Emulator) {
+        if self.capacity == 0 { return; }
+
+        let snapshot = emulator.snapshot();
+        if let Some(newest) = self.newest.take() {
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(Delta::between(&newest, &snapshot));
+        }
+        self.newest = Some(snapshot);
+    }
+
+    // Restores the previous recorded frame; returns false once the history is exhausted.
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> Result<bool, StateError> {
+        let (delta, newest) = match (self.history.pop_back(), self.newest.as_mut()) {
+            (Some(delta), Some(newest)) => (delta, newest),
+            _ => return Ok(false),
+        };
+
+        delta.apply(newest);
+        emulator.restore(newest)?;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.history.iter().map(Delta::size).sum::<usize>()
+            + self.newest.as_ref().map_or(0, Vec::len)
+    }
+}
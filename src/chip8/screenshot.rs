@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::checksum::{crc32, crc32_update};
+use super::palette::Palette;
+use super::video::Video;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// A scaled copy of the display kept as palette indices, written as an indexed png
+// or expanded to rgb for ppm.
+#[derive(Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    palette: Palette,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn capture(video: &Video, palette: &Palette, scale: u32) -> Self {
+        let scale = scale.max(1) as usize;
+        Self::capture_size(video, palette, video.width() * scale, video.height() * scale)
+    }
+
+    // Stretches the display to a fixed size, so recordings keep their size when a
+    // program switches between low and high resolution.
+    pub fn capture_size(video: &Video, palette: &Palette, width: usize, height: usize) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = y * video.height() / height;
+            pixels.extend((0..width).map(|x| video.color(x * video.width() / width, row)));
+        }
+        Self { width, height, palette: *palette, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    // One palette index per pixel, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn rgb(&self) -> Vec<u8> {
+        self.pixels.iter()
+            .flat_map(|&index| {
+                let (r, g, b) = self.palette.color(index);
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    // Writes a ppm for a `.ppm` extension and a png otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&path)?);
+        match path.as_ref().extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("ppm") => self.write_ppm(&mut out)?,
+            _ => self.write_png(&mut out)?,
+        }
+        out.flush()
+    }
+
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.rgb())
+    }
+
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&PNG_SIGNATURE)?;
+        write_chunk(out, b"IHDR", &self.png_header())?;
+        write_chunk(out, b"PLTE", &self.png_palette())?;
+        write_chunk(out, b"IDAT", &self.png_data())?;
+        write_chunk(out, b"IEND", &[])
+    }
+
+    // Two bits per pixel, indexed color.
+    pub fn png_header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[2, 3, 0, 0, 0]);
+        header
+    }
+
+    pub fn png_palette(&self) -> Vec<u8> {
+        self.palette.colors.iter().flat_map(|&(r, g, b)| [r, g, b]).collect()
+    }
+
+    pub fn png_data(&self) -> Vec<u8> {
+        zlib_stored(&self.scanlines())
+    }
+
+    // Rows start with filter type 0 and pack four pixels into a byte, leftmost first.
+    fn scanlines(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.height * (self.width.div_ceil(4) + 1));
+        for row in self.pixels.chunks(self.width) {
+            data.push(0);
+            data.extend(row.chunks(4).map(|pixels| {
+                pixels.iter().enumerate().fold(0, |byte, (n, &index)| byte | (index & 0x3) << (6 - 2 * n))
+            }));
+        }
+        data
+    }
+}
+
+pub fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32_update(crc32(kind), data).to_be_bytes())
+}
+
+// Wraps data in a zlib stream of uncompressed deflate blocks. Frames are small and
+// packed two bits a pixel, so skipping compression keeps this free of dependencies.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
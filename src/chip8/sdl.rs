@@ -0,0 +1,323 @@
+extern crate sdl2;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::video::{FullscreenType, WindowContext};
+
+use super::audio::{pattern_bit, pattern_rate, Tone};
+use super::filter::{DisplayFilter, Filter};
+use super::palette::Palette;
+use super::frontend::{Audio, Command, Display, Input};
+use super::keyboard::Keyboard;
+use super::video::Video;
+
+struct Buzzer {
+    tone: Tone,
+    on: bool,
+    phase: f32,
+    sample_rate: f32,
+    pattern: Option<([u8; 16], u8)>,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            match self.pattern {
+                Some((pattern, pitch)) => {
+                    let bit = pattern_bit(&pattern, self.phase as usize);
+                    *sample = if !self.on { 0.0 } else if bit { self.tone.volume } else { -self.tone.volume };
+                    self.phase = (self.phase + pattern_rate(pitch) / self.sample_rate) % 128.0;
+                }
+                None => {
+                    *sample = if self.on { self.tone.sample(self.phase) } else { 0.0 };
+                    self.phase = (self.phase + self.tone.frequency / self.sample_rate) % 1.0;
+                }
+            }
+        }
+    }
+}
+
+// Square pixels of the largest whole size that fits, centered with black bars
+// around the display when the window has a different shape.
+struct PixelSize {
+    size: u32,
+    x: i32,
+    y: i32,
+}
+
+impl PixelSize {
+    fn fit(window: (u32, u32), resolution: (usize, usize)) -> Self {
+        let (width, height) = (resolution.0 as u32, resolution.1 as u32);
+        let size = (window.0 / width).min(window.1 / height).max(1);
+        Self {
+            size,
+            x: (window.0 as i32 - (width * size) as i32) / 2,
+            y: (window.1 as i32 - (height * size) as i32) / 2,
+        }
+    }
+}
+
+pub struct SdlFrontend {
+    canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    event_pump: sdl2::EventPump,
+    audio_device: Option<AudioDevice<Buzzer>>,
+    bindings: [(u8, Keycode); 16],
+    pixinfo: PixelSize,
+    resolution: (usize, usize),
+    palette: Palette,
+    filter: DisplayFilter,
+    textures: &'static TextureCreator<WindowContext>,
+    // One texel per chip8 pixel, recreated when the resolution changes.
+    texture: Option<Texture<'static>>,
+}
+
+impl SdlFrontend {
+    const LORES: (usize, usize) = (Video::LORES_WIDTH, Video::LORES_HEIGHT);
+
+    // (Chip8 key, keyboard key)
+    const BINDINGS: [(u8, Keycode); 16] = [
+        (0x1, Keycode::Num1),
+        (0x2, Keycode::Num2),
+        (0x3, Keycode::Num3),
+        (0xC, Keycode::Num4),
+
+        (0x4, Keycode::Q),
+        (0x5, Keycode::W),
+        (0x6, Keycode::E),
+        (0xD, Keycode::R),
+
+        (0x7, Keycode::A),
+        (0x8, Keycode::S),
+        (0x9, Keycode::D),
+        (0xE, Keycode::F),
+
+        (0xA, Keycode::Z),
+        (0x0, Keycode::X),
+        (0xB, Keycode::C),
+        (0xF, Keycode::V),
+    ];
+
+    pub const SCALE: u32 = 10;
+
+    // Opens a window `scale` times the low resolution display.
+    pub fn new(tone: Tone, scale: u32) -> Self {
+        let scale = scale.max(1);
+        let width = Self::LORES.0 as u32 * scale;
+        let height = Self::LORES.1 as u32 * scale;
+
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem.window("rusty-chip-8", width, height)
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+
+        let mut canvas = window.into_canvas().build().unwrap();
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.present();
+        let output_size = canvas.output_size().unwrap_or((width, height));
+        // Textures borrow their creator, which is kept for the rest of the program so
+        // the frontend can hold on to its texture.
+        let textures = Box::leak(Box::new(canvas.texture_creator()));
+
+        let audio_device = Self::open_audio(&sdl_context, tone);
+        if let Err(error) = &audio_device {
+            eprintln!("audio disabled: {}", error);
+        }
+
+        Self {
+            canvas,
+            event_pump: sdl_context.event_pump().unwrap(),
+            audio_device: audio_device.ok(),
+            bindings: Self::BINDINGS,
+            pixinfo: PixelSize::fit(output_size, Self::LORES),
+            resolution: Self::LORES,
+            palette: Palette::new(),
+            filter: DisplayFilter::default(),
+            textures,
+            texture: None,
+        }
+    }
+
+    fn open_audio(sdl_context: &sdl2::Sdl, tone: Tone) -> Result<AudioDevice<Buzzer>, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            Buzzer { tone, on: false, phase: 0.0, sample_rate: spec.freq as f32, pattern: None }
+        })?;
+        device.resume();
+        Ok(device)
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter.set_filter(filter);
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let mode = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        if let Err(error) = self.canvas.window_mut().set_fullscreen(mode) {
+            eprintln!("could not change fullscreen mode: {}", error);
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.canvas.window().fullscreen_state() == FullscreenType::Off;
+        self.set_fullscreen(fullscreen);
+    }
+
+    fn fit(&mut self) {
+        if let Ok(output_size) = self.canvas.output_size() {
+            self.pixinfo = PixelSize::fit(output_size, self.resolution);
+        }
+    }
+
+    fn draw(&mut self) {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        if let Err(error) = self.copy_frame() {
+            eprintln!("could not draw the frame: {}", error);
+        }
+        self.canvas.present();
+    }
+
+    // Uploads the filtered frame into the streaming texture and stretches it over the
+    // letterboxed area.
+    fn copy_frame(&mut self) -> Result<(), String> {
+        let (width, height) = self.filter.size();
+        if width == 0 || height == 0 { return Ok(()); }
+
+        let (width, height) = (width as u32, height as u32);
+        let textures = self.textures;
+        let texture = match &mut self.texture {
+            Some(texture) if texture.query().width == width && texture.query().height == height => texture,
+            texture => texture.insert(
+                textures.create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                    .map_err(|error| error.to_string())?
+            ),
+        };
+        texture.update(None, self.filter.rgb(), width as usize * 3).map_err(|error| error.to_string())?;
+
+        let size = self.pixinfo.size;
+        let rect = Rect::new(self.pixinfo.x, self.pixinfo.y, width * size, height * size);
+        self.canvas.copy(texture, None, rect)
+    }
+
+    // F1-F9 load the numbered save state slot, Shift+F1-F9 save to it.
+    fn state_slot(keycode: Keycode) -> Option<u8> {
+        let slots = [
+            Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5,
+            Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9,
+        ];
+        slots.iter().position(|&slot| slot == keycode).map(|index| index as u8 + 1)
+    }
+
+    fn binding(&self, keycode: Keycode) -> Option<u8> {
+        self.bindings.iter().find_map(|&(int, ext)| {
+            if keycode == ext { Some(int) } else { None }
+        })
+    }
+}
+
+impl Display for SdlFrontend {
+    fn refresh(&mut self, video: &Video) {
+        let resolution = (video.width(), video.height());
+        self.filter.apply(video, &self.palette);
+        if resolution != self.resolution {
+            self.resolution = resolution;
+            self.fit();
+        }
+        self.draw();
+    }
+
+    fn palette(&self) -> Palette {
+        self.palette
+    }
+}
+
+impl Input for SdlFrontend {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+
+        for event in events {
+            match event {
+                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    commands.push(Command::Quit);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Backspace), repeat: false, .. } => {
+                    commands.push(Command::Rewind(true));
+                },
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => {
+                    commands.push(Command::Rewind(false));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    commands.push(Command::Screenshot);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    self.toggle_fullscreen();
+                },
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    self.fit();
+                    self.draw();
+                },
+                Event::KeyDown { keycode: Some(keycode), keymod, repeat, .. } => {
+                    if let Some(slot) = Self::state_slot(keycode) {
+                        if repeat { continue; }
+
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            commands.push(Command::SaveState(slot));
+                        } else {
+                            commands.push(Command::LoadState(slot));
+                        }
+                        continue;
+                    }
+
+                    if let Some(internal_number) = self.binding(keycode) {
+                        keyboard.press(internal_number);
+                    }
+                },
+                Event::KeyUp { keycode: Some(keycode), ..} => {
+                    if let Some(internal_number) = self.binding(keycode) {
+                        keyboard.release(internal_number);
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        commands
+    }
+}
+
+impl Audio for SdlFrontend {
+    fn set_buzzer(&mut self, on: bool) {
+        if let Some(device) = &mut self.audio_device {
+            device.lock().on = on;
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        if let Some(device) = &mut self.audio_device {
+            let mut buzzer = device.lock();
+            buzzer.pattern = pattern;
+            buzzer.phase = 0.0;
+        }
+    }
+}
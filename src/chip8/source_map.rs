@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Maps rom addresses to assembler source lines. The file has one entry per line,
+// `<address> <source path> <line>` with the address in hex, blank lines and `#` comments ignored.
+#[derive(Clone, Default)]
+pub struct SourceMap {
+    lines: BTreeMap<usize, (PathBuf, u32)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(&path)?;
+        let base = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, base).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    // Relative source paths are resolved against `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, String> {
+        let mut map = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let invalid = || format!("source map line {}: expected `address path line`", index + 1);
+            let mut fields = line.split_whitespace();
+            let address = fields.next()
+                .and_then(|address| usize::from_str_radix(address.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(invalid)?;
+            let number = fields.next_back().and_then(|number| number.parse().ok()).ok_or_else(invalid)?;
+            let source: Vec<&str> = fields.collect();
+            if source.is_empty() { return Err(invalid()); }
+
+            map.insert(address, base.join(source.join(" ")), number);
+        }
+        Ok(map)
+    }
+
+    pub fn insert<P: Into<PathBuf>>(&mut self, address: usize, source: P, line: u32) {
+        self.lines.insert(address, (source.into(), line));
+    }
+
+    pub fn line(&self, address: usize) -> Option<(&Path, u32)> {
+        self.lines.get(&address).map(|(source, line)| (source.as_path(), *line))
+    }
+
+    // Lowest address generated for a source line.
+    pub fn address(&self, source: &Path, line: u32) -> Option<usize> {
+        self.lines.iter()
+            .find(|(_, (path, number))| *number == line && same_file(path, source))
+            .map(|(&address, _)| address)
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
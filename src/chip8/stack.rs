@@ -0,0 +1,98 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone)]
+pub struct Stack {
+    values: [u16; 16],
+    pointer: usize,
+}
+
+impl Stack {
+    pub fn new() -> Self {
+        Self {
+            values: [0; 16],
+            pointer: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: u16) -> Option<()> {
+        if self.is_full() { return None; }
+
+        self.values[self.pointer] = value;
+        self.pointer += 1;
+        Some(())
+    }
+
+    pub fn pop(&mut self) -> Option<u16> {
+        if self.pointer == 0 { return None; }
+
+        self.pointer -= 1;
+        Some(self.values[self.pointer])
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pointer == self.values.len()
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn set_pointer(&mut self, pointer: usize) -> Option<()> {
+        if pointer > self.values.len() { return None; }
+
+        self.pointer = pointer;
+        Some(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[u16] {
+        &self.values[..self.pointer]
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.pointer as u8);
+        for value in self.values.iter() { writer.u16(*value); }
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let pointer = reader.u8()? as usize;
+        if pointer > self.values.len() { return Err(StateError::Invalid("stack pointer")); }
+
+        self.pointer = pointer;
+        for value in self.values.iter_mut() { *value = reader.u16()?; }
+        Ok(())
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let top = if self.pointer == 0 { 0 } else { self.values[self.pointer - 1] };
+        write!(fmt, " stack_p: {}, stack_value: {:#x}, ", self.pointer, top)
+    }
+}
+
+impl Index<usize> for Stack {
+    type Output = u16;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.values[i]
+    }
+}
+
+impl IndexMut<usize> for Stack {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.values[i]
+    }
+}
@@ -0,0 +1,183 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::checksum::crc32;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io(error) => write!(fmt, "{}", error),
+            StateError::BadMagic => write!(fmt, "unrecognized file type"),
+            StateError::UnsupportedVersion(version) => write!(fmt, "unsupported file version {}", version),
+            StateError::ChecksumMismatch => write!(fmt, "checksum mismatch"),
+            StateError::Truncated => write!(fmt, "file is truncated"),
+            StateError::Invalid(what) => write!(fmt, "invalid {}", what),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(error: io::Error) -> Self {
+        StateError::Io(error)
+    }
+}
+
+// Little endian field writer for state payloads.
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u128(&mut self, value: u128) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.buffer.extend_from_slice(value);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    buffer: &'a [u8],
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buffer: &'a [u8], version: u16) -> Self {
+        Self { buffer, version }
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.buffer.len() < len { return Err(StateError::Truncated); }
+
+        let (head, tail) = self.buffer.split_at(len);
+        self.buffer = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let mut raw = [0; 2];
+        raw.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(raw))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut raw = [0; 4];
+        raw.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(raw))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut raw = [0; 8];
+        raw.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, StateError> {
+        let mut raw = [0; 16];
+        raw.copy_from_slice(self.bytes(16)?);
+        Ok(u128::from_le_bytes(raw))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+// File layout: magic, version (u16), payload length (u32), payload, crc32 of payload.
+// Version 2 added the random generator state.
+pub const MAGIC: &[u8; 4] = b"C8SS";
+pub const VERSION: u16 = 2;
+
+pub fn wrap(payload: &[u8]) -> Vec<u8> {
+    wrap_as(MAGIC, VERSION, payload)
+}
+
+// Same envelope for other file kinds, told apart by their magic.
+pub fn wrap_as(magic: &[u8; 4], version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.bytes(magic);
+    writer.u16(version);
+    writer.u32(payload.len() as u32);
+    writer.bytes(payload);
+    writer.u32(crc32(payload));
+    writer.into_inner()
+}
+
+// Checks the envelope and returns the payload along with its version.
+pub fn unwrap(data: &[u8]) -> Result<(&[u8], u16), StateError> {
+    unwrap_as(MAGIC, VERSION, data)
+}
+
+pub fn unwrap_as<'a>(magic: &[u8; 4], latest: u16, data: &'a [u8]) -> Result<(&'a [u8], u16), StateError> {
+    let mut reader = StateReader::new(data, 0);
+    if reader.bytes(4)? != magic { return Err(StateError::BadMagic); }
+
+    let version = reader.u16()?;
+    if version == 0 || version > latest { return Err(StateError::UnsupportedVersion(version)); }
+
+    let len = reader.u32()? as usize;
+    let payload = reader.bytes(len)?;
+    if reader.u32()? != crc32(payload) { return Err(StateError::ChecksumMismatch); }
+
+    Ok((payload, version))
+}
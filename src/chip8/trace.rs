@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::error::CpuError;
+use super::instruction::Instruction;
+use super::opcode::Opcode;
+use super::Emulator;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TraceLevel {
+    Off,
+    // Key events and faults.
+    Info,
+    // Every executed instruction as well.
+    Debug,
+    // Instructions along with the memory they wrote.
+    Trace,
+}
+
+impl TraceLevel {
+    pub const NAMES: [&'static str; 4] = ["off", "info", "debug", "trace"];
+}
+
+impl FromStr for TraceLevel {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "off" => Ok(TraceLevel::Off),
+            "info" => Ok(TraceLevel::Info),
+            "debug" => Ok(TraceLevel::Debug),
+            "trace" => Ok(TraceLevel::Trace),
+            _ => Err(format!("unknown trace level: {}", name)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceFilter {
+    pub addresses: Option<RangeInclusive<usize>>,
+    // Mnemonics as printed by `Instruction::mnemonic`, e.g. DRW or CALL.
+    pub mnemonics: Option<Vec<String>>,
+    pub keys: bool,
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        Self { addresses: None, mnemonics: None, keys: true }
+    }
+
+    // "200-2ff" in hex, or a single address.
+    pub fn parse_range(text: &str) -> Result<RangeInclusive<usize>, String> {
+        let parse = |value: &str| usize::from_str_radix(value.trim().trim_start_matches("0x"), 16)
+            .map_err(|_| format!("invalid address range: {}", text));
+        match text.split_once('-') {
+            Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+            None => parse(text).map(|address| address..=address),
+        }
+    }
+
+    pub fn parse_mnemonics(text: &str) -> Vec<String> {
+        text.split(',').map(|mnemonic| mnemonic.trim().to_ascii_uppercase()).collect()
+    }
+
+    fn matches(&self, pc: usize, instruction: &Instruction) -> bool {
+        self.addresses.as_ref().is_none_or(|range| range.contains(&pc))
+            && self.mnemonics.as_ref().is_none_or(|mnemonics| {
+                mnemonics.iter().any(|mnemonic| mnemonic == instruction.mnemonic())
+            })
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Writes one `key=value` line per event. Instruction lines carry the opcode at `pc`
+// and the machine state after it ran:
+//   cycle=12 pc=0x204 op=600c mnemonic=LD v=0c000000000000000000000000000000 i=0x22a sp=0
+// followed by ` mem=0x300:01,0x301:02` for memory writes at the trace level.
+pub struct Tracer {
+    level: TraceLevel,
+    filter: TraceFilter,
+    out: Box<dyn Write + Send>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + Send + 'static>(out: W, level: TraceLevel) -> Self {
+        Self { level, filter: TraceFilter::new(), out: Box::new(out) }
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P, level: TraceLevel) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), level))
+    }
+
+    pub fn level(&self) -> TraceLevel {
+        self.level
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn instruction(&mut self, emulator: &Emulator, pc: usize, opcode: Opcode, i: u16, instruction: &Instruction) {
+        if self.level < TraceLevel::Debug || !self.filter.matches(pc, instruction) { return; }
+
+        let mut line = format!(
+            "cycle={} pc={:#05x} op={:04x} mnemonic={} {}",
+            emulator.cycles(), pc, opcode.number(), instruction.mnemonic(), emulator.state()
+        );
+
+        if self.level >= TraceLevel::Trace {
+            if let Some(written) = written_range(instruction, i) {
+                let memory = emulator.memory().as_slice();
+                let writes: Vec<String> = written.filter(|&address| address < memory.len())
+                    .map(|address| format!("{:#05x}:{:02x}", address, memory[address]))
+                    .collect();
+                line.push_str(&format!(" mem={}", writes.join(",")));
+            }
+        }
+        self.write_line(&line);
+    }
+
+    pub fn key(&mut self, cycle: u64, key: u8, down: bool) {
+        if self.level < TraceLevel::Info || !self.filter.keys { return; }
+
+        let state = if down { "down" } else { "up" };
+        self.write_line(&format!("cycle={} event=key key={:x} state={}", cycle, key, state));
+    }
+
+    pub fn fault(&mut self, cycle: u64, error: &CpuError) {
+        if self.level < TraceLevel::Info { return; }
+
+        self.write_line(&format!("cycle={} pc={:#05x} event=fault error=\"{}\"", cycle, error.pc(), error));
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if writeln!(self.out, "{}", line).is_err() {
+            self.level = TraceLevel::Off;
+        }
+    }
+}
+
+// Memory an instruction stores to, given I before it ran.
+fn written_range(instruction: &Instruction, i: u16) -> Option<std::ops::Range<usize>> {
+    let i = i as usize;
+    match *instruction {
+        Instruction::LdB { .. } => Some(i..i + 3),
+        Instruction::LdIVx { x } => Some(i..i + x as usize + 1),
+        Instruction::SaveRange { x, y } => Some(i..i + (x as i8 - y as i8).unsigned_abs() as usize + 1),
+        _ => None,
+    }
+}
@@ -0,0 +1,386 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Field {
+    Cycle,
+    Pc,
+    Opcode,
+    // All sixteen registers packed into one hex string, as in our own traces.
+    Registers,
+    Register(usize),
+    I,
+    Sp,
+    // Comma separated `address:value` pairs.
+    Writes,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let field = match name.as_str() {
+            "cycle" => Field::Cycle,
+            "pc" => Field::Pc,
+            "op" => Field::Opcode,
+            "v" => Field::Registers,
+            "i" => Field::I,
+            "sp" => Field::Sp,
+            "mem" => Field::Writes,
+            _ => {
+                let index = name.strip_prefix('v').and_then(|x| usize::from_str_radix(x, 16).ok())?;
+                if name.len() != 2 { return None; }
+                Field::Register(index)
+            }
+        };
+        Some(field)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Column {
+    // The value of a `key=value` or `key:value` token.
+    Key(String),
+    // The nth whitespace separated token, counting from 1.
+    Position(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Mapping {
+    field: Field,
+    column: Column,
+    radix: u32,
+}
+
+// Where to find each value on a trace line. A mapping file has one entry per line,
+// `<field> <column> [hex|dec]`, with blank lines and `#` comments ignored:
+//   pc     PC
+//   v0     #3
+//   cycle  #1  dec
+// Fields are cycle, pc, op, v (all registers as one hex string), v0-vf, i, sp and mem.
+// A column is a key name or `#n` for the nth token. Values are hex unless marked dec.
+// `state before` declares that lines show registers before the instruction ran rather
+// than after it, as our own traces do.
+#[derive(Clone, Debug)]
+pub struct TraceFormat {
+    mappings: Vec<Mapping>,
+    state_before: bool,
+}
+
+impl TraceFormat {
+    // The format written by `Tracer`.
+    pub fn native() -> Self {
+        let key = |field, name: &str, radix| Mapping { field, column: Column::Key(name.to_string()), radix };
+        Self {
+            mappings: vec![
+                key(Field::Cycle, "cycle", 10),
+                key(Field::Pc, "pc", 16),
+                key(Field::Opcode, "op", 16),
+                key(Field::Registers, "v", 16),
+                key(Field::I, "i", 16),
+                key(Field::Sp, "sp", 10),
+                key(Field::Writes, "mem", 16),
+            ],
+            state_before: false,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut format = Self { mappings: Vec::new(), state_before: false };
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let invalid = || format!("trace format line {}: expected `field column [hex|dec]`", index + 1);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["state", "before"] => format.state_before = true,
+                ["state", "after"] => format.state_before = false,
+                [field, column] | [field, column, _] => {
+                    let field = Field::parse(field).ok_or_else(invalid)?;
+                    let column = match column.strip_prefix('#') {
+                        Some(position) => match position.parse() {
+                            Ok(position) if position > 0 => Column::Position(position),
+                            _ => return Err(invalid()),
+                        },
+                        None => Column::Key(column.to_string()),
+                    };
+                    let radix = match words.get(2) {
+                        None | Some(&"hex") => 16,
+                        Some(&"dec") => 10,
+                        Some(_) => return Err(invalid()),
+                    };
+                    format.mappings.push(Mapping { field, column, radix });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        if !format.mappings.iter().any(|mapping| mapping.field == Field::Pc) {
+            return Err("trace format does not map pc".to_string());
+        }
+        Ok(format)
+    }
+
+    // Lines missing any mapped value other than memory writes are not instructions
+    // and yield None, which skips headers, key events and faults.
+    fn record(&self, number: usize, text: &str) -> Option<TraceRecord> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let mut record = TraceRecord { line: number, text: text.to_string(), ..TraceRecord::default() };
+
+        for mapping in self.mappings.iter() {
+            let value = match &mapping.column {
+                Column::Key(key) => tokens.iter().find_map(|token| {
+                    let (name, value) = token.split_at(token.find(['=', ':'])?);
+                    if name.eq_ignore_ascii_case(key) { Some(&value[1..]) } else { None }
+                }),
+                Column::Position(position) => tokens.get(position - 1).copied(),
+            };
+            let value = value.map(|value| value.trim_end_matches(','));
+
+            if mapping.field == Field::Writes {
+                record.writes = value.map(parse_writes);
+                continue;
+            }
+            let value = value?;
+            match mapping.field {
+                Field::Registers => {
+                    if value.len() != 32 { return None; }
+                    for (register, digits) in record.registers.iter_mut().zip(value.as_bytes().chunks(2)) {
+                        *register = Some(u8::from_str_radix(str::from_utf8(digits).ok()?, 16).ok()?);
+                    }
+                }
+                field => {
+                    let number = parse_number(value, mapping.radix)?;
+                    match field {
+                        Field::Cycle => record.cycle = Some(number),
+                        Field::Pc => record.pc = Some(number as usize),
+                        Field::Opcode => record.opcode = Some(number as u16),
+                        Field::Register(x) => record.registers[x] = Some(number as u8),
+                        Field::I => record.i = Some(number as u16),
+                        Field::Sp => record.sp = Some(number as usize),
+                        Field::Registers | Field::Writes => unreachable!(),
+                    }
+                }
+            }
+        }
+        Some(record)
+    }
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+fn parse_number(text: &str, radix: u32) -> Option<u64> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')).unwrap_or(text);
+    u64::from_str_radix(digits, radix).ok()
+}
+
+fn parse_writes(text: &str) -> Vec<(usize, u8)> {
+    text.split(',')
+        .filter_map(|write| {
+            let (address, value) = write.split_once(':')?;
+            Some((parse_number(address, 16)? as usize, parse_number(value, 16)? as u8))
+        })
+        .collect()
+}
+
+// One executed instruction, holding the register state after it ran. Values the
+// trace format does not map are None and never compared.
+#[derive(Clone, Default, Debug)]
+pub struct TraceRecord {
+    pub line: usize,
+    pub text: String,
+    pub cycle: Option<u64>,
+    pub pc: Option<usize>,
+    pub opcode: Option<u16>,
+    pub registers: [Option<u8>; 16],
+    pub i: Option<u16>,
+    pub sp: Option<usize>,
+    pub writes: Option<Vec<(usize, u8)>>,
+}
+
+impl TraceRecord {
+    fn take_state(&mut self, from: &TraceRecord) {
+        self.registers = from.registers;
+        self.i = from.i;
+        self.sp = from.sp;
+    }
+}
+
+pub struct TraceReader<R: BufRead> {
+    lines: io::Lines<R>,
+    format: TraceFormat,
+    number: usize,
+    pending: Option<TraceRecord>,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P, format: TraceFormat) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?), format))
+    }
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(input: R, format: TraceFormat) -> Self {
+        Self { lines: input.lines(), format, number: 0, pending: None }
+    }
+
+    fn next_record(&mut self) -> Option<io::Result<TraceRecord>> {
+        for line in &mut self.lines {
+            self.number += 1;
+            match line {
+                Ok(line) => if let Some(record) = self.format.record(self.number, &line) {
+                    return Some(Ok(record));
+                },
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        None
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.format.state_before { return self.next_record(); }
+
+        // The state after an instruction is the state shown before the next one,
+        // so the last instruction of the trace has no known state.
+        let mut record = match self.pending.take() {
+            Some(record) => record,
+            None => match self.next_record()? {
+                Ok(record) => record,
+                Err(error) => return Some(Err(error)),
+            },
+        };
+        match self.next_record() {
+            Some(Ok(next)) => {
+                record.take_state(&next);
+                self.pending = Some(next);
+            }
+            Some(Err(error)) => return Some(Err(error)),
+            None => record.take_state(&TraceRecord::default()),
+        }
+        Some(Ok(record))
+    }
+}
+
+pub struct Difference {
+    pub field: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+pub enum TraceDiff {
+    Match { instructions: usize },
+    Diverged {
+        instruction: usize,
+        previous: Option<TraceRecord>,
+        ours: TraceRecord,
+        theirs: TraceRecord,
+        differences: Vec<Difference>,
+    },
+    // One trace ran out of instructions first; the other one's next record is kept.
+    Ended { instruction: usize, ours: Option<TraceRecord>, theirs: Option<TraceRecord> },
+}
+
+impl TraceDiff {
+    pub fn is_match(&self) -> bool {
+        matches!(self, TraceDiff::Match { .. })
+    }
+}
+
+// Walks both traces in step and stops at the first instruction where they disagree.
+pub fn diff_traces<A, B>(mut ours: A, mut theirs: B) -> io::Result<TraceDiff>
+where
+    A: Iterator<Item = io::Result<TraceRecord>>,
+    B: Iterator<Item = io::Result<TraceRecord>>,
+{
+    let mut previous = None;
+    let mut instruction = 0;
+    loop {
+        instruction += 1;
+        let (ours, theirs) = match (ours.next().transpose()?, theirs.next().transpose()?) {
+            (None, None) => return Ok(TraceDiff::Match { instructions: instruction - 1 }),
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            (ours, theirs) => return Ok(TraceDiff::Ended { instruction, ours, theirs }),
+        };
+
+        let differences = differences(&ours, &theirs);
+        if !differences.is_empty() {
+            return Ok(TraceDiff::Diverged { instruction, previous, ours, theirs, differences });
+        }
+        previous = Some(ours);
+    }
+}
+
+fn differences(ours: &TraceRecord, theirs: &TraceRecord) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let mut compare = |field: String, ours: Option<String>, theirs: Option<String>| {
+        if let (Some(ours), Some(theirs)) = (ours, theirs) {
+            if ours != theirs { differences.push(Difference { field, ours, theirs }); }
+        }
+    };
+
+    compare("pc".to_string(), ours.pc.map(|pc| format!("{:#05x}", pc)), theirs.pc.map(|pc| format!("{:#05x}", pc)));
+    compare("op".to_string(), ours.opcode.map(|op| format!("{:04x}", op)), theirs.opcode.map(|op| format!("{:04x}", op)));
+    for x in 0..16 {
+        let value = |register: Option<u8>| register.map(|value| format!("{:#04x}", value));
+        compare(format!("v{:x}", x), value(ours.registers[x]), value(theirs.registers[x]));
+    }
+    compare("i".to_string(), ours.i.map(|i| format!("{:#05x}", i)), theirs.i.map(|i| format!("{:#05x}", i)));
+    compare("sp".to_string(), ours.sp.map(|sp| sp.to_string()), theirs.sp.map(|sp| sp.to_string()));
+
+    let writes = |writes: &Option<Vec<(usize, u8)>>| writes.as_ref().map(|writes| {
+        let writes: Vec<String> = writes.iter().map(|(address, value)| format!("{:#05x}:{:02x}", address, value)).collect();
+        writes.join(",")
+    });
+    compare("mem".to_string(), writes(&ours.writes), writes(&theirs.writes));
+    differences
+}
+
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let line = |fmt: &mut fmt::Formatter, side: &str, record: &TraceRecord| {
+            writeln!(fmt, "  {:<10} line {}: {}", side, record.line, record.text)
+        };
+
+        match self {
+            TraceDiff::Match { instructions } => writeln!(fmt, "traces match for {} instructions", instructions),
+            TraceDiff::Diverged { instruction, previous, ours, theirs, differences } => {
+                let cycle = ours.cycle.unwrap_or(*instruction as u64);
+                writeln!(fmt, "traces diverge at cycle {} (instruction {})", cycle, instruction)?;
+                if let Some(previous) = previous {
+                    line(fmt, "last match", previous)?;
+                }
+                line(fmt, "ours", ours)?;
+                line(fmt, "theirs", theirs)?;
+                for difference in differences.iter() {
+                    writeln!(fmt, "  {}: ours {}, theirs {}", difference.field, difference.ours, difference.theirs)?;
+                }
+                Ok(())
+            }
+            TraceDiff::Ended { instruction, ours, theirs } => {
+                let (ended, other, record) = match (ours, theirs) {
+                    (Some(record), _) => ("theirs", "ours", record),
+                    (_, Some(record)) => ("ours", "theirs", record),
+                    (None, None) => unreachable!(),
+                };
+                writeln!(fmt, "{} ends after {} instructions, {} continues", ended, instruction - 1, other)?;
+                line(fmt, other, record)
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::state::{StateError, StateReader, StateWriter};
+
+// Rows are stored most significant bit first: pixel `x` of a row is bit `127 - x`,
+// so the low resolution screen only uses the upper 64 bits of each row.
+#[derive(Clone)]
+pub struct Video {
+    planes: [[u128; 64]; 2],
+    plane_mask: u8,
+    hires: bool,
+}
+
+impl Video {
+    pub const LORES_WIDTH: usize = 64;
+    pub const LORES_HEIGHT: usize = 32;
+    pub const HIRES_WIDTH: usize = 128;
+    pub const HIRES_HEIGHT: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            planes: [[0; 64]; 2],
+            plane_mask: 0x1,
+            hires: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { Self::HIRES_WIDTH } else { Self::LORES_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { Self::HIRES_HEIGHT } else { Self::LORES_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0; 64]; 2];
+    }
+
+    pub fn plane_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.plane_mask = mask & 0x3;
+    }
+
+    pub fn clear(&mut self) {
+        for plane in self.selected_planes() {
+            self.planes[plane] = [0; 64];
+        }
+    }
+
+    // Sprite data for each selected plane follows the previous one, `n` bytes each.
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: u8, y: u8, clip: bool) -> u8 {
+        let per_plane = sprite.len() / self.plane_count().max(1);
+        let mut collision = 0;
+
+        for (index, plane) in self.selected_planes().into_iter().enumerate() {
+            let rows: Vec<u16> = sprite[index * per_plane..(index + 1) * per_plane].iter()
+                .map(|&line| (line as u16) << 8)
+                .collect();
+            collision |= self.draw_rows(plane, &rows, 8, x, y, clip);
+        }
+        collision
+    }
+
+    // Draws 16x16 sprites stored as 32 bytes per selected plane, two per row.
+    pub fn draw_big_sprite(&mut self, sprite: &[u8], x: u8, y: u8, clip: bool) -> u8 {
+        let mut collision = 0;
+
+        for (index, plane) in self.selected_planes().into_iter().enumerate() {
+            let rows: Vec<u16> = sprite[index * 32..(index + 1) * 32].chunks(2)
+                .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+                .collect();
+            collision |= self.draw_rows(plane, &rows, 16, x, y, clip);
+        }
+        collision
+    }
+
+    fn draw_rows(&mut self, plane: usize, rows: &[u16], sprite_width: usize, x: u8, y: u8, clip: bool) -> u8 {
+        let mut collision : u8 = 0;
+        let (width, height) = (self.width(), self.height());
+        let x = x as usize % width;
+        let y = y as usize % height;
+        let memory = &mut self.planes[plane];
+
+        for (sprite_line_index, sprite_row) in rows.iter().enumerate() {
+            let line_num = y + sprite_line_index;
+            if clip && line_num >= height { break; }
+            let line_num = line_num % height;
+
+            for xi in 0..sprite_width {
+                if clip && x + xi >= width { break; }
+
+                if sprite_row & (0x8000 >> xi) != 0 {
+                    let display_bit_p = Self::bit((x + xi) % width);
+
+                    if (memory[line_num] & display_bit_p) > 0 { collision = 1; }
+
+                    memory[line_num] ^= display_bit_p;
+                }
+            }
+        }
+
+        collision
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected_planes() {
+            let memory = &mut self.planes[plane];
+            for line in (0..height).rev() {
+                memory[line] = if line >= n { memory[line - n] } else { 0 };
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected_planes() {
+            let memory = &mut self.planes[plane];
+            for line in 0..height {
+                memory[line] = if line + n < height { memory[line + n] } else { 0 };
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected_planes() {
+            for line in self.planes[plane].iter_mut() {
+                *line = (*line >> n) & mask;
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected_planes() {
+            for line in self.planes[plane].iter_mut() {
+                *line = (*line << n) & mask;
+            }
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.color(x, y) != 0
+    }
+
+    // Palette index of a pixel: bit 0 is set by plane 1 and bit 1 by plane 2.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        let bit = Self::bit(x);
+        (self.planes[0][y] & bit != 0) as u8 | ((self.planes[1][y] & bit != 0) as u8) << 1
+    }
+
+    pub fn rows(&self, plane: usize) -> &[u128] {
+        &self.planes[plane][..self.height()]
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        writer.u8(self.plane_mask);
+        for row in self.planes.iter().flat_map(|plane| plane.iter()) {
+            writer.u128(*row);
+        }
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.hires = reader.bool()?;
+        self.plane_mask = reader.u8()? & 0x3;
+        for row in self.planes.iter_mut().flat_map(|plane| plane.iter_mut()) {
+            *row = reader.u128()?;
+        }
+        Ok(())
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.plane_mask.count_ones() as usize
+    }
+
+    fn selected_planes(&self) -> Vec<usize> {
+        (0..2).filter(|plane| self.plane_mask & (1 << plane) != 0).collect()
+    }
+
+    fn bit(x: usize) -> u128 {
+        1 << (127 - x)
+    }
+
+    fn row_mask(&self) -> u128 {
+        !0 << (128 - self.width())
+    }
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod chip8;
+
+pub use chip8::{Emulator, Opcode};
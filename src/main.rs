@@ -1,8 +1,8 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use clap::{Arg, App};
 
-mod chip8;
+use rusty_chip_8::chip8;
 
 fn main() {
     let opt_matches = App::new("WIP: Rusty Chip8 emulator")
@@ -15,17 +15,487 @@ fn main() {
             .value_name("ROM_PATH")
             .about("path to rom file")
             .takes_value(true))
+        .arg(Arg::new("tone-hz")
+            .long("tone-hz")
+            .value_name("HZ")
+            .about("buzzer frequency")
+            .takes_value(true))
+        .arg(Arg::new("volume")
+            .long("volume")
+            .value_name("VOLUME")
+            .about("buzzer volume, from 0.0 to 1.0")
+            .takes_value(true))
+        .arg(Arg::new("waveform")
+            .long("waveform")
+            .value_name("WAVEFORM")
+            .about("buzzer waveform")
+            .possible_values(&["square", "triangle", "sawtooth", "sine"])
+            .takes_value(true))
+        .arg(Arg::new("scale")
+            .long("scale")
+            .value_name("SCALE")
+            .about("initial window size in screen pixels per chip8 pixel, 10 by default")
+            .takes_value(true))
+        .arg(Arg::new("fullscreen")
+            .long("fullscreen")
+            .about("starts fullscreen, F11 toggles it"))
+        .arg(Arg::new("palette")
+            .long("palette")
+            .value_name("PRESET")
+            .about("display colors, lit pixels on a dark background by default")
+            .possible_values(&chip8::Palette::PRESETS)
+            .takes_value(true))
+        .arg(Arg::new("palette-file")
+            .long("palette-file")
+            .value_name("PALETTE_PATH")
+            .about("reads colors from a file of name=rrggbb lines")
+            .conflicts_with("palette")
+            .takes_value(true))
+        .arg(Arg::new("color")
+            .long("color")
+            .value_name("NAME=RRGGBB")
+            .about("overrides one color: background, foreground, plane2 or overlap")
+            .multiple_occurrences(true)
+            .takes_value(true))
+        .arg(Arg::new("filter")
+            .long("filter")
+            .value_name("FILTER")
+            .about("softens flicker: phosphor fades pixels out, hold keeps them lit for a few frames")
+            .possible_values(&chip8::Filter::NAMES)
+            .takes_value(true))
+        .arg(Arg::new("filter-strength")
+            .long("filter-strength")
+            .value_name("STRENGTH")
+            .about("glow kept per frame from 0 to 1 for phosphor (0.6), frames to hold for hold (3)")
+            .requires("filter")
+            .takes_value(true))
+        .arg(Arg::new("platform")
+            .long("platform")
+            .value_name("PLATFORM")
+            .about("instruction set and display of the target machine")
+            .possible_values(&chip8::Platform::NAMES)
+            .takes_value(true))
+        .arg(Arg::new("quirks")
+            .long("quirks")
+            .value_name("PRESET")
+            .about("quirks preset the rom was written for, the original chip8 behaviour of this emulator by default")
+            .possible_values(&chip8::Quirks::PRESETS)
+            .takes_value(true))
+        .arg(Arg::new("quirk")
+            .long("quirk")
+            .value_name("NAME=on|off")
+            .about("overrides a single quirk of the preset")
+            .multiple_occurrences(true)
+            .takes_value(true))
+        .arg(Arg::new("load-state")
+            .long("load-state")
+            .value_name("STATE_PATH")
+            .about("resumes from a save state of the same rom")
+            .takes_value(true))
+        .arg(Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .about("seeds the random generator for reproducible runs")
+            .takes_value(true))
+        .arg(Arg::new("random")
+            .long("random")
+            .value_name("MODE")
+            .about("random generator, vip follows the original interpreter")
+            .possible_values(&chip8::RandomMode::NAMES)
+            .takes_value(true))
+        .arg(Arg::new("record-movie")
+            .long("record-movie")
+            .value_name("MOVIE_PATH")
+            .about("records keypad input from power-on to a movie file")
+            .conflicts_with_all(&["play-movie", "load-state"])
+            .takes_value(true))
+        .arg(Arg::new("play-movie")
+            .long("play-movie")
+            .value_name("MOVIE_PATH")
+            .about("replays a movie, using the settings it was recorded with")
+            .conflicts_with("load-state")
+            .takes_value(true))
+        .arg(Arg::new("debug")
+            .long("debug")
+            .about("starts paused with a debugger on the terminal"))
+        .arg(Arg::new("gdb")
+            .long("gdb")
+            .value_name("PORT")
+            .about("accepts gdb remote protocol connections on a local port")
+            .conflicts_with("debug")
+            .takes_value(true))
+        .arg(Arg::new("dap")
+            .long("dap")
+            .about("speaks the debug adapter protocol on stdin and stdout")
+            .conflicts_with_all(&["debug", "gdb"]))
+        .arg(Arg::new("dap-port")
+            .long("dap-port")
+            .value_name("PORT")
+            .about("waits for a debug adapter protocol client on a local port")
+            .conflicts_with_all(&["debug", "gdb", "dap"])
+            .takes_value(true))
+        .arg(Arg::new("source-map")
+            .long("source-map")
+            .value_name("MAP_PATH")
+            .about("address to source line map for the debug adapter")
+            .takes_value(true))
+        .arg(Arg::new("trace")
+            .long("trace")
+            .value_name("TRACE_PATH")
+            .about("writes a machine readable execution trace to a file")
+            .takes_value(true))
+        .arg(Arg::new("trace-level")
+            .long("trace-level")
+            .value_name("LEVEL")
+            .about("info logs key events and faults, debug adds instructions, trace adds memory writes")
+            .possible_values(&chip8::TraceLevel::NAMES)
+            .requires("trace")
+            .takes_value(true))
+        .arg(Arg::new("trace-range")
+            .long("trace-range")
+            .value_name("START-END")
+            .about("only traces instructions in this hex address range")
+            .requires("trace")
+            .takes_value(true))
+        .arg(Arg::new("trace-ops")
+            .long("trace-ops")
+            .value_name("MNEMONICS")
+            .about("only traces these instructions, e.g. DRW,CALL,RET")
+            .requires("trace")
+            .takes_value(true))
+        .arg(Arg::new("trace-keys")
+            .long("trace-keys")
+            .value_name("on|off")
+            .about("traces key events, on by default")
+            .possible_values(&["on", "off"])
+            .requires("trace")
+            .takes_value(true))
+        .arg(Arg::new("trace-diff")
+            .long("trace-diff")
+            .value_names(&["OURS", "THEIRS"])
+            .about("compares two traces and reports where they first diverge")
+            .number_of_values(2)
+            .takes_value(true))
+        .arg(Arg::new("trace-format")
+            .long("trace-format")
+            .value_name("FORMAT_PATH")
+            .about("column mapping for a trace written by another emulator")
+            .requires("trace-diff")
+            .takes_value(true))
+        .arg(Arg::new("screenshot-after-frames")
+            .long("screenshot-after-frames")
+            .value_names(&["FRAMES", "IMAGE_PATH"])
+            .about("saves a png, or a ppm by extension, of the screen after this many frames")
+            .number_of_values(2)
+            .takes_value(true))
+        .arg(Arg::new("screenshot-scale")
+            .long("screenshot-scale")
+            .value_name("SCALE")
+            .about("pixels per chip8 pixel in screenshots, 1 by default")
+            .takes_value(true))
+        .arg(Arg::new("record-video")
+            .long("record-video")
+            .value_name("VIDEO_PATH")
+            .about("records the screen to a .gif or .apng, or raw rgb24 frames to stdout with -")
+            .takes_value(true))
+        .arg(Arg::new("record-format")
+            .long("record-format")
+            .value_name("FORMAT")
+            .about("overrides the format guessed from the video path")
+            .possible_values(&chip8::RecordingFormat::NAMES)
+            .requires("record-video")
+            .takes_value(true))
+        .arg(Arg::new("video-scale")
+            .long("video-scale")
+            .value_name("SCALE")
+            .about("pixels per chip8 pixel in recordings, 1 by default")
+            .requires("record-video")
+            .takes_value(true))
+        .arg(Arg::new("headless")
+            .long("headless")
+            .about("runs without a window for --frames or until the screenshot is taken")
+            .conflicts_with_all(&["debug", "gdb", "dap", "dap-port"]))
+        .arg(Arg::new("frames")
+            .long("frames")
+            .value_name("FRAMES")
+            .about("number of frames to run headless")
+            .requires("headless")
+            .takes_value(true))
+        .arg(Arg::new("rewind-seconds")
+            .long("rewind-seconds")
+            .value_name("SECONDS")
+            .about("history kept for rewinding with backspace, 0 disables it")
+            .takes_value(true))
         .get_matches();
 
+    if let Some(mut paths) = opt_matches.values_of("trace-diff") {
+        let (ours_path, theirs_path) = (paths.next().unwrap(), paths.next().unwrap());
+        let format = match opt_matches.value_of("trace-format") {
+            Some(format_path) => chip8::TraceFormat::load(format_path).unwrap_or_else(|error| {
+                eprintln!("could not load {}: {}", format_path, error);
+                std::process::exit(2);
+            }),
+            None => chip8::TraceFormat::native(),
+        };
+        let open = |path: &str, format| chip8::TraceReader::open(path, format).unwrap_or_else(|error| {
+            eprintln!("could not open {}: {}", path, error);
+            std::process::exit(2);
+        });
+
+        let ours = open(ours_path, chip8::TraceFormat::native());
+        let theirs = open(theirs_path, format);
+        match chip8::diff_traces(ours, theirs) {
+            Ok(diff) => {
+                print!("{}", diff);
+                std::process::exit(if diff.is_match() { 0 } else { 1 });
+            }
+            Err(error) => {
+                eprintln!("could not read traces: {}", error);
+                std::process::exit(2);
+            }
+        }
+    }
+
     if let Some(rom_path) = opt_matches.value_of("rom") {
-        let mut file = File::open(&rom_path).expect("no rom file found");
+        let mut file = File::open(rom_path).expect("no rom file found");
         let mut rom_buffer = Vec::new();
         file.read_to_end(&mut rom_buffer).expect("buffer overflow");
 
+        let platform: chip8::Platform = opt_matches.value_of("platform")
+            .map(|name| name.parse().unwrap())
+            .unwrap_or_default();
+        let mut quirks = opt_matches.value_of("quirks")
+            .map(|preset| chip8::Quirks::preset(preset).unwrap())
+            .unwrap_or_else(|| platform.default_quirks());
+        for assignment in opt_matches.values_of("quirk").into_iter().flatten() {
+            if let Err(error) = quirks.apply_override(assignment) {
+                eprintln!("{} (known quirks: {})", error, chip8::Quirks::NAMES.join(", "));
+                std::process::exit(2);
+            }
+        }
+
         let mut cpu = chip8::Emulator::new();
-        cpu.load_rom(&rom_buffer, );
+        cpu.set_platform(platform);
+        cpu.set_quirks(quirks);
+        if opt_matches.is_present("seed") || opt_matches.is_present("random") {
+            let mode = opt_matches.value_of("random")
+                .map(|name| name.parse().unwrap())
+                .unwrap_or_default();
+            let seed = opt_matches.value_of("seed")
+                .map(|seed| seed.parse().expect("invalid seed"))
+                .unwrap_or_else(|| cpu.random().seed());
+            cpu.set_random(chip8::Random::new(mode, seed));
+        }
+
+        let movie = opt_matches.value_of("play-movie").map(|movie_path| {
+            chip8::Movie::load(movie_path).unwrap_or_else(|error| {
+                eprintln!("could not load {}: {}", movie_path, error);
+                std::process::exit(1);
+            })
+        });
+        if let Some(movie) = &movie {
+            movie.configure(&mut cpu);
+        }
+
+        cpu.load_rom(&rom_buffer).unwrap_or_else(|error| {
+            eprintln!("could not load {}: {}", rom_path, error);
+            std::process::exit(1);
+        });
         cpu.load_font();
-        cpu.run();
+        cpu.set_state_slots(rom_path);
+        if let Some(trace_path) = opt_matches.value_of("trace") {
+            let level = opt_matches.value_of("trace-level")
+                .map(|level| level.parse().unwrap())
+                .unwrap_or(chip8::TraceLevel::Debug);
+            let mut filter = chip8::TraceFilter::new();
+            if let Some(range) = opt_matches.value_of("trace-range") {
+                filter.addresses = Some(chip8::TraceFilter::parse_range(range).unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(2);
+                }));
+            }
+            filter.mnemonics = opt_matches.value_of("trace-ops").map(chip8::TraceFilter::parse_mnemonics);
+            filter.keys = opt_matches.value_of("trace-keys") != Some("off");
+
+            let mut tracer = chip8::Tracer::create(trace_path, level).unwrap_or_else(|error| {
+                eprintln!("could not create {}: {}", trace_path, error);
+                std::process::exit(1);
+            });
+            tracer.set_filter(filter);
+            cpu.set_tracer(Some(tracer));
+        }
+        let scale = opt_matches.value_of("screenshot-scale")
+            .map(|scale| scale.parse().expect("invalid screenshot scale"))
+            .unwrap_or(1);
+        cpu.set_screenshots(rom_path, scale);
+        let screenshot_after = opt_matches.values_of("screenshot-after-frames").map(|mut values| {
+            let frames: u64 = values.next().unwrap().parse().expect("invalid screenshot frame count");
+            (frames, values.next().unwrap())
+        });
+        if let Some((frames, image_path)) = screenshot_after {
+            cpu.screenshot_after(frames, image_path);
+        }
+        let headless_frames = opt_matches.value_of("frames")
+            .map(|frames| frames.parse::<u64>().expect("invalid frame count"))
+            .or_else(|| screenshot_after.map(|(frames, _)| frames));
+        if opt_matches.is_present("headless") && headless_frames.is_none() {
+            eprintln!("--headless needs --frames or --screenshot-after-frames");
+            std::process::exit(2);
+        }
+
+        if let Some(video_path) = opt_matches.value_of("record-video") {
+            let format = opt_matches.value_of("record-format")
+                .map(|format| format.parse().unwrap())
+                .unwrap_or_else(|| chip8::RecordingFormat::from_path(video_path));
+            if video_path == "-" && opt_matches.is_present("dap") {
+                eprintln!("raw frames can not share stdout with the debug adapter");
+                std::process::exit(2);
+            }
+            let scale = opt_matches.value_of("video-scale")
+                .map(|scale| scale.parse().expect("invalid video scale"))
+                .unwrap_or(1);
+            let recorder = chip8::Recorder::create(video_path, format, scale).unwrap_or_else(|error| {
+                eprintln!("could not create {}: {}", video_path, error);
+                std::process::exit(1);
+            });
+            cpu.set_recorder(Some(recorder));
+        }
+        if let Some(seconds) = opt_matches.value_of("rewind-seconds") {
+            cpu.set_rewind_seconds(seconds.parse().expect("invalid rewind length"));
+        }
+
+        if let Some(state_path) = opt_matches.value_of("load-state") {
+            if let Err(error) = cpu.load_state_file(state_path) {
+                eprintln!("could not load {}: {}", state_path, error);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(movie) = movie {
+            if let Err(error) = cpu.play_movie(movie) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        if opt_matches.is_present("record-movie") {
+            cpu.record_movie();
+        }
+
+        let mut tone = chip8::Tone::new();
+        if let Some(frequency) = opt_matches.value_of("tone-hz") {
+            tone.frequency = frequency.parse().expect("invalid buzzer frequency");
+        }
+        if let Some(volume) = opt_matches.value_of("volume") {
+            tone.volume = volume.parse::<f32>().expect("invalid buzzer volume").clamp(0.0, 1.0);
+        }
+        if let Some(waveform) = opt_matches.value_of("waveform") {
+            tone.waveform = waveform.parse().unwrap();
+        }
+
+        let mut palette = match opt_matches.value_of("palette-file") {
+            Some(palette_path) => chip8::Palette::load(palette_path).unwrap_or_else(|error| {
+                eprintln!("could not load {}: {}", palette_path, error);
+                std::process::exit(2);
+            }),
+            None => opt_matches.value_of("palette")
+                .map(|preset| chip8::Palette::preset(preset).unwrap())
+                .unwrap_or_default(),
+        };
+        for assignment in opt_matches.values_of("color").into_iter().flatten() {
+            if let Err(error) = palette.apply_override(assignment) {
+                eprintln!("{} (known colors: {})", error, chip8::Palette::NAMES.join(", "));
+                std::process::exit(2);
+            }
+        }
+
+        let result = if opt_matches.is_present("headless") {
+            match cpu.run_headless(headless_frames.unwrap(), &palette) {
+                Err(chip8::MovieError::Cpu(error)) => Err(error),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    Ok(())
+                }
+                Ok(()) => Ok(()),
+            }
+        } else {
+            let scale = opt_matches.value_of("scale")
+                .map(|scale| scale.parse().expect("invalid window scale"))
+                .unwrap_or(chip8::SdlFrontend::SCALE);
+            let mut frontend = chip8::SdlFrontend::new(tone, scale);
+            if opt_matches.is_present("fullscreen") {
+                frontend.set_fullscreen(true);
+            }
+            frontend.set_palette(palette);
+            if let Some(filter) = opt_matches.value_of("filter") {
+                let filter = match opt_matches.value_of("filter-strength") {
+                    Some(strength) => format!("{}={}", filter, strength),
+                    None => filter.to_string(),
+                };
+                frontend.set_filter(filter.parse().unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(2);
+                }));
+            }
+            if opt_matches.is_present("debug") {
+                let mut debugger = chip8::Debugger::new();
+                let mut repl = chip8::Repl::new(io::stdin());
+                cpu.debug(&mut frontend, &mut debugger, &mut repl)
+            } else if let Some(port) = opt_matches.value_of("gdb") {
+                let port: u16 = port.parse().expect("invalid gdb port");
+                let mut stub = chip8::GdbStub::bind(("127.0.0.1", port)).unwrap_or_else(|error| {
+                    eprintln!("could not listen on port {}: {}", port, error);
+                    std::process::exit(1);
+                });
+                eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+                let mut debugger = chip8::Debugger::new();
+                debugger.resume(&cpu, chip8::Resume::Continue);
+                cpu.debug(&mut frontend, &mut debugger, &mut stub)
+            } else if opt_matches.is_present("dap") || opt_matches.is_present("dap-port") {
+                let mut server = match opt_matches.value_of("dap-port") {
+                    Some(port) => {
+                        let port: u16 = port.parse().expect("invalid debug adapter port");
+                        eprintln!("waiting for a debug adapter client on 127.0.0.1:{}", port);
+                        chip8::DapServer::listen(("127.0.0.1", port)).unwrap_or_else(|error| {
+                            eprintln!("could not listen on port {}: {}", port, error);
+                            std::process::exit(1);
+                        })
+                    }
+                    None => chip8::DapServer::new(io::stdin(), io::stdout()),
+                };
+                if let Some(map_path) = opt_matches.value_of("source-map") {
+                    match chip8::SourceMap::load(map_path) {
+                        Ok(source_map) => server.set_source_map(source_map),
+                        Err(error) => eprintln!("could not load {}: {}", map_path, error),
+                    }
+                }
+                let mut debugger = chip8::Debugger::new();
+                cpu.debug(&mut frontend, &mut debugger, &mut server)
+            } else {
+                cpu.run(&mut frontend)
+            }
+        };
+
+        cpu.flush_trace();
+        if let Some(recorded) = cpu.finish_recording() {
+            let video_path = opt_matches.value_of("record-video").unwrap();
+            match recorded {
+                Ok(frames) => eprintln!("recorded {} frames to {}", frames, video_path),
+                Err(error) => eprintln!("could not finish {}: {}", video_path, error),
+            }
+        }
+
+        if let Some(movie_path) = opt_matches.value_of("record-movie") {
+            if let Some(movie) = cpu.finish_movie() {
+                match movie.save(movie_path) {
+                    Ok(()) => eprintln!("recorded {} frames to {}", movie.length(), movie_path),
+                    Err(error) => eprintln!("could not save {}: {}", movie_path, error),
+                }
+            }
+        }
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
     } else {
         println!("ROM file not specified. Try run with --help flag")
     }
@@ -0,0 +1,84 @@
+use rusty_chip_8::chip8::{Audio, Command, Display, Emulator, Headless, Input, Keyboard, Platform, Video};
+
+// Sets ST to 10, waits for DT to count down from 20 and exits.
+const BEEP: [u8; 16] = [
+    0x60, 0x0A, // LD V0, 10
+    0xF0, 0x18, // LD ST, V0
+    0x61, 0x14, // LD V1, 20
+    0xF1, 0x15, // LD DT, V1
+    0xF1, 0x07, // LD V1, DT
+    0x31, 0x00, // SE V1, 0
+    0x12, 0x08, // JP 0x208
+    0x00, 0xFD, // EXIT
+];
+
+#[test]
+fn sound_timer_drives_the_buzzer_frame_by_frame() {
+    let mut emulator = Emulator::new();
+    emulator.set_platform(Platform::SuperChip);
+    emulator.load_rom(&BEEP).unwrap();
+    let mut frontend = Headless::new();
+    emulator.run(&mut frontend).unwrap();
+
+    // ST is set and ticks once in the first frame, so it stays on for 9 more ticks.
+    let audio = frontend.audio();
+    let transitions: Vec<usize> = audio.history().windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] != pair[1])
+        .map(|(frame, _)| frame + 1)
+        .collect();
+    assert!(audio.history()[0]);
+    assert_eq!(transitions, vec![9]);
+    assert_eq!(audio.frames_on(), 9);
+    assert!(!audio.is_on());
+    assert_eq!(audio.history().len() as u64, frontend.refreshes());
+    assert_eq!(frontend.refreshes(), 21);
+}
+
+// Headless that quits after a number of frames, for roms that never exit.
+struct Frames(Headless, u64);
+
+impl Display for Frames {
+    fn refresh(&mut self, video: &Video) {
+        self.0.refresh(video);
+    }
+}
+
+impl Input for Frames {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> Vec<Command> {
+        if self.0.refreshes() >= self.1 { self.0.quit(); }
+        self.0.poll(keyboard)
+    }
+}
+
+impl Audio for Frames {
+    fn set_buzzer(&mut self, on: bool) {
+        self.0.set_buzzer(on);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        self.0.set_pattern(pattern);
+    }
+}
+
+fn load_state_and_run(platform: Platform) -> Headless {
+    let mut emulator = Emulator::new();
+    emulator.set_platform(platform);
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    let state = emulator.save_state();
+    emulator.load_state(&state).unwrap();
+
+    let mut frontend = Frames(Headless::new(), 2);
+    emulator.run(&mut frontend).unwrap();
+    frontend.0
+}
+
+#[test]
+fn loading_a_state_keeps_the_tone_outside_xo_chip() {
+    assert_eq!(load_state_and_run(Platform::Chip8).audio().pattern(), None);
+}
+
+#[test]
+fn loading_a_state_restores_the_xo_chip_pattern() {
+    assert_eq!(load_state_and_run(Platform::XoChip).audio().pattern(), Some(([0; 16], 64)));
+}
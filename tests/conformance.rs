@@ -0,0 +1,154 @@
+// Runs the test roms in `roms/` headlessly under the default quirks and every preset and
+// compares a hash of the final framebuffer with the golden results in
+// `tests/conformance.golden`. Only screens that show a rom's own pass result are kept
+// there; combinations a rom is known to fail under are marked `xfail`, still run, but
+// not compared. After an intended change in behaviour, inspect the frames printed for
+// the failing cases and regenerate the hashes with
+// `UPDATE_GOLDEN=1 cargo test --test conformance`, which leaves `xfail` entries alone.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+use rusty_chip_8::chip8::{crc32, Emulator, Quirks, Random, Video};
+
+// (rom, frames to run)
+const ROMS: [(&str, u32); 6] = [
+    ("test_opcode.ch8", 120),
+    ("BC_test.ch8", 120),
+    ("c8_test.c8", 120),
+    ("IBM Logo.ch8", 60),
+    ("Chip8 Picture.ch8", 60),
+    ("Division Test [Sergey Naydenov, 2010].ch8", 300),
+];
+
+const GOLDEN: &str = "tests/conformance.golden";
+const XFAIL: &str = "xfail";
+
+// `default` stands for `Quirks::default()`, followed by the named presets.
+fn presets() -> impl Iterator<Item = &'static str> {
+    iter::once("default").chain(Quirks::PRESETS.iter().copied())
+}
+
+fn run(rom: &[u8], quirks: Quirks, frames: u32) -> Result<Video, String> {
+    let mut emulator = Emulator::new();
+    emulator.set_quirks(quirks);
+    emulator.set_random(Random::default());
+    emulator.load_rom(rom)?;
+    emulator.load_font();
+    for frame in 0..frames {
+        emulator.run_frame().map_err(|error| format!("frame {}: {}", frame, error))?;
+    }
+    Ok(emulator.video().clone())
+}
+
+fn hash(video: &Video) -> u32 {
+    let mut pixels = vec![video.width() as u8, video.height() as u8];
+    for y in 0..video.height() {
+        pixels.extend((0..video.width()).map(|x| video.color(x, y)));
+    }
+    crc32(&pixels)
+}
+
+fn render(video: &Video) -> String {
+    (0..video.height())
+        .map(|y| (0..video.width()).map(|x| if video.pixel(x, y) { '#' } else { '.' }).collect::<String>() + "\n")
+        .collect()
+}
+
+// Splits a `<crc32|xfail> <preset> <rom>` entry; blank lines and `#` comments are None.
+fn parse_entry(line: &str) -> Option<(String, String, String)> {
+    if line.trim().is_empty() || line.starts_with('#') { return None; }
+
+    let mut fields = line.splitn(3, ' ');
+    let (expected, preset, rom) = (fields.next()?, fields.next()?, fields.next()?);
+    Some((expected.to_string(), preset.to_string(), rom.to_string()))
+}
+
+fn read_golden(text: &str) -> BTreeMap<(String, String), String> {
+    text.lines()
+        .filter_map(parse_entry)
+        .map(|(expected, preset, rom)| ((rom, preset), expected))
+        .collect()
+}
+
+// Replaces the hashes of existing entries and appends new ones, keeping comments and
+// `xfail` entries as they are.
+fn update_golden(text: &str, actual: &[(String, String, String)]) -> String {
+    let mut hashes: BTreeMap<(String, String), String> = actual.iter()
+        .map(|(hash, preset, rom)| ((rom.clone(), preset.clone()), hash.clone()))
+        .collect();
+
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        match parse_entry(line) {
+            Some((expected, preset, rom)) => match hashes.remove(&(rom.clone(), preset.clone())) {
+                Some(hash) if expected != XFAIL => lines.push(format!("{} {} {}", hash, preset, rom)),
+                _ => lines.push(line.to_string()),
+            },
+            None => lines.push(line.to_string()),
+        }
+    }
+    for (hash, preset, rom) in actual {
+        if hashes.contains_key(&(rom.clone(), preset.clone())) {
+            lines.push(format!("{} {} {}", hash, preset, rom));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[test]
+fn test_roms_match_golden_framebuffers() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let golden_path = root.join(GOLDEN);
+    let golden_text = fs::read_to_string(&golden_path).unwrap_or_default();
+    let golden = read_golden(&golden_text);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut results = Vec::new();
+    let mut failures = Vec::new();
+    for &(name, frames) in ROMS.iter() {
+        let rom = fs::read(root.join("roms").join(name)).unwrap_or_else(|error| panic!("{}: {}", name, error));
+        let mut passing = 0;
+        for preset in presets() {
+            let expected = golden.get(&(name.to_string(), preset.to_string()));
+            let quirks = Quirks::preset(preset).unwrap_or_default();
+            let video = match run(&rom, quirks, frames) {
+                Ok(video) => video,
+                Err(error) => {
+                    println!("FAIL    {:<7} {}", preset, name);
+                    failures.push(format!("{} ({}): {}", name, preset, error));
+                    continue;
+                }
+            };
+
+            let actual = format!("{:08x}", hash(&video));
+            let status = match expected {
+                Some(expected) if expected == XFAIL => "xfail",
+                Some(expected) if *expected == actual => "pass",
+                _ if update => "updated",
+                _ => "FAIL",
+            };
+            println!("{:<7} {:<7} {}", status, preset, name);
+
+            match status {
+                "FAIL" => {
+                    let expected = expected.map(String::as_str).unwrap_or("nothing");
+                    failures.push(format!("{} ({}): expected {}, got {}\n{}", name, preset, expected, actual, render(&video)));
+                }
+                "xfail" => {}
+                _ => passing += 1,
+            }
+            results.push((actual, preset.to_string(), name.to_string()));
+        }
+        if passing == 0 {
+            failures.push(format!("{} passes under no preset", name));
+        }
+    }
+
+    if update {
+        fs::write(&golden_path, update_golden(&golden_text, &results)).unwrap();
+    }
+    assert!(failures.is_empty(), "{} conformance failures:\n{}", failures.len(), failures.join("\n"));
+}
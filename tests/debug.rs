@@ -0,0 +1,47 @@
+use rusty_chip_8::chip8::{DebugClient, Debugger, Emulator, Headless, Resume, StopReason};
+
+// Steps a number of instructions one at a time, the way `step <count>` in the repl
+// does, and ends the session once they are done.
+struct Stepper {
+    remaining: u32,
+    started: bool,
+}
+
+impl DebugClient for Stepper {
+    fn poll(&mut self, debugger: &mut Debugger, emulator: &mut Emulator) -> bool {
+        if !self.started {
+            self.started = true;
+            debugger.resume(emulator, Resume::Step);
+        }
+        !debugger.is_paused() || self.remaining > 0
+    }
+
+    fn stopped(&mut self, debugger: &mut Debugger, emulator: &mut Emulator, reason: StopReason) {
+        assert_eq!(reason, StopReason::Step);
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            debugger.resume(emulator, Resume::Step);
+        }
+    }
+}
+
+// Stepping keeps to the frame budget, so the timers tick once per frame's worth of steps.
+#[test]
+fn stepping_advances_timers_with_the_frames_it_spans() {
+    let mut emulator = Emulator::new();
+    emulator.set_hz(6000);
+    let rom = [
+        0x60, 0xFF, // LD V0, 255
+        0xF0, 0x15, // LD DT, V0
+        0x12, 0x04, // JP 0x204
+    ];
+    emulator.load_rom(&rom).unwrap();
+    let mut frontend = Headless::new();
+    let mut client = Stepper { remaining: 600, started: false };
+    emulator.debug(&mut frontend, &mut Debugger::new(), &mut client).unwrap();
+
+    assert_eq!(client.remaining, 0);
+    assert_eq!(emulator.cycles(), 600);
+    assert_eq!(emulator.delay_timer(), 255 - 6);
+    assert_eq!(frontend.refreshes(), 6);
+}
@@ -0,0 +1,26 @@
+use rusty_chip_8::chip8::{Emulator, Opcode, Platform, Quirks};
+
+fn xo_chip() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.set_platform(Platform::XoChip);
+    emulator.set_quirks(Quirks::modern());
+    emulator
+}
+
+// Fx55/Fx65 ending on the last byte of the 64 KiB memory leave I wrapped to 0.
+#[test]
+fn load_store_at_top_of_memory_wraps_i() {
+    for &opcode in [0xFF55, 0xFF65].iter() {
+        let mut emulator = xo_chip();
+        emulator.set_i(0xFFF0);
+        emulator.exec_opcode(&Opcode::new(opcode)).unwrap();
+        assert_eq!(emulator.i(), 0x0000, "{:04x}", opcode);
+    }
+}
+
+#[test]
+fn load_store_past_top_of_memory_faults() {
+    let mut emulator = xo_chip();
+    emulator.set_i(0xFFF1);
+    assert!(emulator.exec_opcode(&Opcode::new(0xFF55)).is_err());
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::Path;
+
+use rusty_chip_8::chip8::{Emulator, Movie, MovieError, StateError};
+
+const FRAMES: u64 = 200;
+
+fn brix() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/BRIX")).unwrap()
+}
+
+// Records BRIX with the paddle moved left and then right, giving the movie and the
+// final state hash.
+fn record() -> (Movie, u32) {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&brix()).unwrap();
+    emulator.load_font();
+    emulator.record_movie();
+    for frame in 0..FRAMES {
+        match frame {
+            20 => emulator.keyboard_mut().press(0x4),
+            50 => emulator.keyboard_mut().release(0x4),
+            80 => emulator.keyboard_mut().press(0x6),
+            120 => emulator.keyboard_mut().release(0x6),
+            _ => {}
+        }
+        emulator.movie_frame().unwrap();
+    }
+    (emulator.finish_movie().unwrap(), emulator.state_hash())
+}
+
+fn replayer(movie: &Movie) -> Emulator {
+    let mut emulator = Emulator::new();
+    movie.configure(&mut emulator);
+    emulator.load_rom(&brix()).unwrap();
+    emulator.load_font();
+    emulator
+}
+
+#[test]
+fn movie_round_trips_through_bytes() {
+    let (movie, _) = record();
+    assert_eq!(movie.length(), FRAMES);
+    assert_eq!(movie.inputs(), &[(20, 0x0010), (50, 0), (80, 0x0040), (120, 0)][..]);
+    assert_eq!(movie.hashes().len(), 4);
+
+    let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    assert_eq!(loaded.rom_crc, movie.rom_crc);
+    assert_eq!(loaded.platform, movie.platform);
+    assert_eq!(loaded.quirks, movie.quirks);
+    assert_eq!(loaded.random, movie.random);
+    assert_eq!(loaded.hz, movie.hz);
+    assert_eq!(loaded.length(), movie.length());
+    assert_eq!(loaded.inputs(), movie.inputs());
+    assert_eq!(loaded.hashes(), movie.hashes());
+    assert_eq!(loaded.to_bytes(), movie.to_bytes());
+}
+
+#[test]
+fn movie_replays_to_the_recorded_state() {
+    let (movie, hash) = record();
+    let mut emulator = replayer(&movie);
+    emulator.play_movie(Movie::from_bytes(&movie.to_bytes()).unwrap()).unwrap();
+    emulator.replay_movie().unwrap();
+    assert_eq!(emulator.state_hash(), hash);
+}
+
+#[test]
+fn replay_detects_a_desync() {
+    let (movie, _) = record();
+    let mut emulator = replayer(&movie);
+    emulator.play_movie(movie).unwrap();
+    for _ in 0..30 {
+        emulator.movie_frame().unwrap();
+    }
+
+    // Keys come from the movie, so only a change to the machine itself throws it off.
+    emulator.memory_mut()[0xF00_usize] ^= 0xFF;
+    let result = emulator.replay_movie();
+    assert!(matches!(result, Err(MovieError::Desync { frame: 60, .. })), "{:?}", result);
+}
+
+#[test]
+fn verify_hash_checks_only_checkpoints() {
+    let (movie, _) = record();
+    let (frame, hash) = movie.hashes()[1];
+    assert!(movie.verify_hash(frame, hash).is_ok());
+    assert!(movie.verify_hash(frame + 1, hash ^ 1).is_ok());
+    match movie.verify_hash(frame, hash ^ 1) {
+        Err(MovieError::Desync { frame: at, expected, actual }) => {
+            assert_eq!((at, expected, actual), (frame, hash, hash ^ 1));
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn movie_for_another_rom_is_refused() {
+    let (movie, _) = record();
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    assert!(matches!(emulator.play_movie(movie), Err(MovieError::RomMismatch { .. })));
+}
+
+#[test]
+fn damaged_movie_files_are_rejected() {
+    let (movie, _) = record();
+    let bytes = movie.to_bytes();
+
+    assert!(matches!(Movie::from_bytes(&Emulator::new().save_state()), Err(StateError::BadMagic)));
+    assert!(matches!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(StateError::Truncated)));
+
+    let mut corrupted = bytes.clone();
+    corrupted[12] ^= 0xFF;
+    assert!(matches!(Movie::from_bytes(&corrupted), Err(StateError::ChecksumMismatch)));
+}
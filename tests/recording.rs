@@ -0,0 +1,139 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use rusty_chip_8::chip8::{crc32, Image, Palette, Recorder, RecordingFormat, Video};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Splits a png into (type, data) chunks, checking the signature and every chunk crc.
+fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(png[..8], SIGNATURE);
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let data = &rest[8..8 + len];
+        let crc = u32::from_be_bytes([rest[8 + len], rest[9 + len], rest[10 + len], rest[11 + len]]);
+        assert_eq!(crc, crc32(&rest[4..8 + len]), "crc of {}", String::from_utf8_lossy(&kind));
+        chunks.push((kind, data.to_vec()));
+        rest = &rest[12 + len..];
+    }
+    chunks
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+// Decodes a zlib stream made only of stored deflate blocks.
+fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+    assert_eq!(stream[..2], [0x78, 0x01]);
+    assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+    let mut data = Vec::new();
+    let mut at = 2;
+    loop {
+        let header = stream[at];
+        assert_eq!(header & 0x06, 0, "not a stored block");
+        let len = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+        let nlen = u16::from_le_bytes([stream[at + 3], stream[at + 4]]);
+        assert_eq!(len, !nlen);
+        data.extend_from_slice(&stream[at + 5..at + 5 + len as usize]);
+        at += 5 + len as usize;
+        if header & 1 == 1 { break; }
+    }
+    let adler = u32::from_be_bytes([stream[at], stream[at + 1], stream[at + 2], stream[at + 3]]);
+    assert_eq!(adler, adler32(&data));
+    assert_eq!(at + 4, stream.len());
+    data
+}
+
+// Unpacks two bit indexed scanlines with filter type 0.
+fn unpack(scanlines: &[u8], width: usize) -> Vec<u8> {
+    scanlines.chunks(width.div_ceil(4) + 1)
+        .flat_map(|row| {
+            assert_eq!(row[0], 0);
+            row[1..].iter().flat_map(|&byte| (0..4).map(move |n| byte >> (6 - 2 * n) & 0x3)).take(width)
+        })
+        .collect()
+}
+
+fn video(frame: u8) -> Video {
+    let mut video = Video::new();
+    video.draw_sprite(&[0xF0, 0x90, 0xF0], frame, frame / 2, true);
+    video
+}
+
+#[test]
+fn png_decodes_to_the_captured_pixels() {
+    let palette = Palette::new();
+    // Rows of 257 bytes, so the image data needs more than one stored block.
+    for &(width, height) in [(64, 32), (1021, 300)].iter() {
+        let image = Image::capture_size(&video(5), &palette, width, height);
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], b"PLTE", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, image.png_header());
+        assert_eq!(unpack(&inflate_stored(&chunks[2].1), width), image.pixels());
+    }
+}
+
+#[test]
+fn apng_counts_the_frames_it_wrote() {
+    let path = env::temp_dir().join(format!("rusty-chip-8-{}.png", process::id()));
+    let palette = Palette::new();
+    let mut recorder = Recorder::create(path.to_str().unwrap(), RecordingFormat::Apng, 1).unwrap();
+    // Three different frames, the second one held for 10 frames.
+    recorder.frame(&video(0), &palette).unwrap();
+    for _ in 0..10 {
+        recorder.frame(&video(8), &palette).unwrap();
+    }
+    recorder.frame(&video(16), &palette).unwrap();
+    recorder.finish().unwrap();
+    let png = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(recorder.frames(), 12);
+
+    let chunks = chunks(&png);
+    let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+    assert_eq!(kinds, vec![
+        &b"IHDR"[..], b"PLTE", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND",
+    ]);
+
+    let actl = &chunks[2].1;
+    assert_eq!(u32::from_be_bytes([actl[0], actl[1], actl[2], actl[3]]), 3);
+
+    // Sequence numbers run on through fcTL and fdAT; delays are in 60ths of a second.
+    let mut sequence = Vec::new();
+    let mut delays = Vec::new();
+    let mut images = Vec::new();
+    for (kind, data) in chunks.iter() {
+        match kind {
+            b"fcTL" => {
+                delays.push(u16::from_be_bytes([data[20], data[21]]));
+                assert_eq!(u16::from_be_bytes([data[22], data[23]]), 60);
+            }
+            b"IDAT" => {
+                images.push(unpack(&inflate_stored(data), 64));
+                continue;
+            }
+            b"fdAT" => images.push(unpack(&inflate_stored(&data[4..]), 64)),
+            _ => continue,
+        }
+        sequence.push(u32::from_be_bytes([data[0], data[1], data[2], data[3]]));
+    }
+    assert_eq!(sequence, (0..5).collect::<Vec<u32>>());
+    assert_eq!(delays, vec![1, 10, 1]);
+    for (image, &frame) in images.iter().zip([0, 8, 16].iter()) {
+        assert_eq!(image[..], *Image::capture(&video(frame), &palette, 1).pixels());
+    }
+    assert_eq!(images.len(), 3);
+}
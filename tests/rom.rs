@@ -0,0 +1,21 @@
+use rusty_chip_8::chip8::{Emulator, Platform};
+
+#[test]
+fn rom_filling_memory_loads() {
+    let mut emulator = Emulator::new();
+    let rom = vec![0xAB; 4096 - Emulator::ROM_START as usize];
+    emulator.load_rom(&rom).unwrap();
+    assert_eq!(emulator.memory()[4095_usize], 0xAB);
+}
+
+#[test]
+fn rom_larger_than_memory_is_rejected() {
+    let mut emulator = Emulator::new();
+    let rom = vec![0xAB; 4096 - Emulator::ROM_START as usize + 1];
+    assert!(emulator.load_rom(&rom).is_err());
+    assert_eq!(emulator.memory()[Emulator::ROM_START as usize], 0);
+
+    // The same rom fits the 64 KiB of XO-CHIP.
+    emulator.set_platform(Platform::XoChip);
+    emulator.load_rom(&rom).unwrap();
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::Path;
+
+use rusty_chip_8::chip8::{crc32, Emulator, Platform, StateError};
+
+// File layout: magic (4), version (2), payload length (4), payload, crc32 (4).
+const PAYLOAD: usize = 10;
+
+fn running(frames: u32) -> Emulator {
+    let rom = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/Particle Demo [zeroZshadow, 2008].ch8")).unwrap();
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&rom).unwrap();
+    emulator.load_font();
+    for _ in 0..frames {
+        emulator.run_frame().unwrap();
+    }
+    emulator
+}
+
+// Recomputes the checksum after the payload was edited.
+fn reseal(data: &mut [u8]) {
+    let end = data.len() - 4;
+    let crc = crc32(&data[PAYLOAD..end]);
+    data[end..].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[test]
+fn state_round_trips() {
+    let mut emulator = running(30);
+    let state = emulator.save_state();
+    let hash = emulator.state_hash();
+
+    for _ in 0..30 {
+        emulator.run_frame().unwrap();
+    }
+    assert_ne!(emulator.state_hash(), hash);
+
+    emulator.load_state(&state).unwrap();
+    assert_eq!(emulator.state_hash(), hash);
+    assert_eq!(emulator.save_state(), state);
+}
+
+#[test]
+fn state_with_bad_magic_is_rejected() {
+    let mut state = running(1).save_state();
+    state[0] = b'X';
+    assert!(matches!(Emulator::new().load_state(&state), Err(StateError::BadMagic)));
+}
+
+#[test]
+fn state_from_a_newer_version_is_rejected() {
+    let mut state = running(1).save_state();
+    state[4..6].copy_from_slice(&99u16.to_le_bytes());
+    assert!(matches!(Emulator::new().load_state(&state), Err(StateError::UnsupportedVersion(99))));
+}
+
+#[test]
+fn corrupted_state_is_rejected() {
+    let mut state = running(1).save_state();
+    let last = state.len() - 5;
+    state[last] ^= 0xFF;
+    assert!(matches!(Emulator::new().load_state(&state), Err(StateError::ChecksumMismatch)));
+}
+
+#[test]
+fn truncated_state_is_rejected() {
+    let state = running(1).save_state();
+    for &len in [0, 3, PAYLOAD, state.len() - 1].iter() {
+        assert!(matches!(Emulator::new().load_state(&state[..len]), Err(StateError::Truncated)), "{} bytes", len);
+    }
+}
+
+#[test]
+fn failed_load_leaves_the_emulator_alone() {
+    let mut emulator = running(10);
+    let hash = emulator.state_hash();
+    let mut state = running(20).save_state();
+    state.pop();
+    assert!(emulator.load_state(&state).is_err());
+    assert_eq!(emulator.state_hash(), hash);
+}
+
+// The platform comes first in the payload; memory has to be the size that platform has.
+#[test]
+fn state_with_memory_of_another_platform_is_rejected() {
+    let mut state = running(1).save_state();
+    state[PAYLOAD] = Platform::XoChip.to_u8();
+    reseal(&mut state);
+    assert!(matches!(Emulator::new().load_state(&state), Err(StateError::Invalid("memory size"))));
+}
+
+#[test]
+fn state_restores_the_platform_memory() {
+    let mut emulator = Emulator::new();
+    emulator.set_platform(Platform::XoChip);
+    emulator.load_rom(&[0x12, 0x00]).unwrap();
+    let state = emulator.save_state();
+
+    let mut restored = Emulator::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.platform(), Platform::XoChip);
+    assert_eq!(restored.memory().size(), 0x10000);
+}